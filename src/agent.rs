@@ -0,0 +1,244 @@
+use crate::{config, crypto, get_vodka_path, Error};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use zeroize::Zeroize;
+
+// the agent keeps the data key unlocked, similar to ssh-agent. one request per connection:
+//   "key\n"  -> "ok <hex data key>\n"
+//   "lock\n" -> "ok\n", then the agent wipes the key and exits
+
+pub fn get_socket_path() -> PathBuf {
+    get_vodka_path("agent.sock")
+}
+
+// data key held by the agent. locked into memory so it never hits swap, wiped on drop
+pub(crate) struct LockedKey(pub(crate) Vec<u8>);
+
+impl LockedKey {
+    pub(crate) fn new(key: Vec<u8>) -> LockedKey {
+        if unsafe { libc::mlock(key.as_ptr() as *const libc::c_void, key.len()) } != 0 {
+            eprintln!("Warning: failed to lock the key into memory");
+        }
+
+        LockedKey(key)
+    }
+}
+
+impl Drop for LockedKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+        unsafe { libc::munlock(self.0.as_ptr() as *const libc::c_void, self.0.len()) };
+    }
+}
+
+// data key from a running agent, if there is one
+pub fn get_key() -> Option<Vec<u8>> {
+    let response = request("key").ok()?;
+    let key = crypto::from_hex(response.strip_prefix("ok ")?)?;
+
+    if key.len() == 32 {
+        Some(key)
+    } else {
+        None
+    }
+}
+
+// returns false if no agent was running
+pub fn lock() -> Result<bool, Error> {
+    if !get_socket_path().exists() {
+        return Ok(false);
+    }
+
+    match request("lock") {
+        Ok(response) => Ok(response == "ok"),
+        Err(Error::IOError(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            fs::remove_file(get_socket_path())?; // stale socket
+            Ok(false)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+fn request(command: &str) -> Result<String, Error> {
+    let mut stream = UnixStream::connect(get_socket_path())?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    writeln!(stream, "{}", command)?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+
+    Ok(response.trim_end().to_string())
+}
+
+// starts `vodka agent --stdin-key` in the background and hands it the key through a pipe
+pub fn spawn(key: Vec<u8>) -> Result<u32, Error> {
+    spawn_with_key("agent", &get_socket_path(), key)
+}
+
+// starts `vodka <subcommand> --stdin-key` in the background, hands it the key and waits for it to listen on socket_path
+pub(crate) fn spawn_with_key(subcommand: &str, socket_path: &Path, key: Vec<u8>) -> Result<u32, Error> {
+    let mut key = LockedKey::new(key);
+
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .arg(subcommand)
+        .arg("--stdin-key")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    let mut child = command.spawn()?;
+    let mut hex_key = crypto::to_hex(&key.0);
+    let written = writeln!(child.stdin.take().unwrap(), "{}", hex_key);
+    hex_key.zeroize();
+    key.0.zeroize();
+    written?;
+
+    // wait for the socket so the next command can use the agent straight away
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(5) {
+        if let Some(status) = child.try_wait()? {
+            return Err(Error::AgentFailed(format!("agent exited with {}", status)));
+        }
+        if UnixStream::connect(socket_path).is_ok() {
+            return Ok(child.id());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    Err(Error::AgentFailed(String::from("agent did not start listening")))
+}
+
+// serves the key until `vodka lock` or agent-timeout seconds without a request (0 = never)
+pub fn serve(key: Vec<u8>) -> Result<(), Error> {
+    let key = LockedKey::new(key);
+    let socket_path = get_socket_path();
+    let listener = bind(&socket_path)?;
+
+    let timeout: u64 = config::get_or("agent-timeout", 900).try_into().expect("Expected positive integer value for agent-timeout");
+    let mut last_used = Instant::now();
+
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                last_used = Instant::now();
+
+                match handle_request(stream, &key) {
+                    Ok(true) => break,
+                    Ok(false) => {},
+                    Err(e) => eprintln!("Error while handling agent request: {}", e),
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                if timeout > 0 && last_used.elapsed() >= Duration::from_secs(timeout) {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            },
+            Err(e) => return Err(Error::from(e)),
+        }
+    }
+
+    fs::remove_file(&socket_path)?;
+
+    Ok(())
+}
+
+// listens on a socket only the current user can access. fails if an agent already listens there
+pub(crate) fn bind(socket_path: &Path) -> Result<UnixListener, Error> {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(Error::AgentFailed(String::from("an agent is already running")));
+        }
+        fs::remove_file(socket_path)?; // stale socket
+    }
+
+    // keep the key out of core dumps and away from ptrace by other processes
+    #[cfg(target_os = "linux")]
+    unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0) };
+
+    // restrictive umask so the socket is never accessible to anyone else, even briefly
+    let old_umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket_path);
+    unsafe { libc::umask(old_umask) };
+    let listener = listener?;
+
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+
+    Ok(listener)
+}
+
+// returns true if the agent should lock
+fn handle_request(mut stream: UnixStream, key: &LockedKey) -> Result<bool, Error> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    if get_peer_uid(&stream)? != unsafe { libc::getuid() } {
+        return Ok(false);
+    }
+
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+
+    match request.trim_end() {
+        "key" => {
+            let mut response = format!("ok {}\n", crypto::to_hex(&key.0));
+            let written = stream.write_all(response.as_bytes());
+            response.zeroize();
+            written?;
+            Ok(false)
+        },
+        "lock" => {
+            stream.write_all(b"ok\n")?;
+            Ok(true)
+        },
+        _ => {
+            stream.write_all(b"error unknown request\n")?;
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn get_peer_uid(stream: &UnixStream) -> Result<libc::uid_t, Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut credentials = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length
+        )
+    };
+
+    if result != 0 {
+        return Err(Error::from(std::io::Error::last_os_error()));
+    }
+
+    Ok(credentials.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn get_peer_uid(stream: &UnixStream) -> Result<libc::uid_t, Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut uid: libc::uid_t = 0;
+    let mut gid: libc::gid_t = 0;
+
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(Error::from(std::io::Error::last_os_error()));
+    }
+
+    Ok(uid)
+}
@@ -0,0 +1,88 @@
+use crate::{config, crypto, store, Attachment, Entry, Error};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+// contents are split so no single blob gets huge, and each chunk is encrypted on its own
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// bytes, attachment-max-size in config.toml. keeps the cellar from growing out of hand
+pub fn max_size() -> u64 {
+    config::get_or("attachment-max-size", 10 * 1024 * 1024).max(0) as u64
+}
+
+// associated data of a chunk, so chunks can't be reordered, dropped or moved to another
+// attachment without failing to decrypt. the name goes last as it may contain anything
+fn chunk_aad(entry_id: i32, name: &str, index: usize, count: usize) -> Vec<u8> {
+    format!("vodka attachment {}/{}/{}/{}", entry_id, index, count, name).into_bytes()
+}
+
+// attaches the file at `path`, under its file name unless `name` is given
+pub fn add_file(entry: &Entry, path: &Path, name: Option<&str>, master_key: &[u8]) -> Result<String, Error> {
+    let name = match name {
+        Some(name) => name.trim().to_string(),
+        None => path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+    };
+    if name.is_empty() {
+        return Err(Error::InvalidEntry(String::from("attachment name cannot be empty")));
+    }
+    if store::get_attachments(entry.id)?.iter().any(|attachment| attachment.name == name) {
+        return Err(Error::InvalidEntry(format!("{}@{} already has an attachment named {}", entry.login, entry.name, name)));
+    }
+
+    // checked before reading so a huge file is never loaded
+    let size = fs::metadata(path)?.len();
+    if size > max_size() {
+        return Err(Error::AttachmentTooLarge(size, max_size()));
+    }
+
+    let content = fs::read(path)?;
+    if content.len() as u64 > max_size() {
+        return Err(Error::AttachmentTooLarge(content.len() as u64, max_size()));
+    }
+
+    let count = content.len().div_ceil(CHUNK_SIZE);
+    let chunks: Vec<Vec<u8>> = content
+        .chunks(CHUNK_SIZE)
+        .enumerate()
+        .map(|(index, chunk)| crypto::encrypt_with_aad(chunk, master_key, &chunk_aad(entry.id, &name, index, count)))
+        .collect();
+
+    store::add_attachment(entry.id, &name, content.len() as u64, &chunks)?;
+
+    Ok(name)
+}
+
+// decrypted content of an attachment. fails if any chunk was tampered with, reordered or is missing
+pub fn read(attachment: &Attachment, master_key: &[u8]) -> Result<Vec<u8>, Error> {
+    let chunks = store::get_attachment_chunks(attachment.id)?;
+    let count = chunks.len();
+
+    let mut content = Vec::with_capacity(attachment.size as usize);
+    for (index, chunk) in chunks.iter().enumerate() {
+        let aad = chunk_aad(attachment.entry_id, &attachment.name, index, count);
+        content.extend(crypto::decrypt_with_aad(chunk, master_key, &aad)?);
+    }
+
+    if content.len() as u64 != attachment.size {
+        return Err(Error::DecryptionFailed);
+    }
+
+    Ok(content)
+}
+
+// writes a decrypted attachment readable by the owner only, also when replacing a file
+pub fn write_file(path: &Path, content: &[u8]) -> Result<(), Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path)?;
+    // mode only applies to new files
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(content)?;
+
+    Ok(())
+}
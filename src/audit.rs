@@ -0,0 +1,162 @@
+use crate::{config, get_absolute_path, strength, unix_time, Entry, EntryKind, Error};
+use crate::breach::BreachDb;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::path::PathBuf;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+// thresholds of an audit, defaults from the [audit] table of config.toml
+pub struct AuditOptions {
+    pub min_score: u8, // passwords scoring lower are reported as weak
+    pub max_age_days: u64, // passwords changed longer ago are reported as old. 0 to skip
+    pub breach_db: Option<PathBuf>, // Pwned Passwords file to look every password up in
+}
+
+impl AuditOptions {
+    pub fn from_config() -> AuditOptions {
+        AuditOptions {
+            min_score: config::get_or("audit.min-score", 3).clamp(0, 4) as u8,
+            max_age_days: config::get_or("audit.max-age-days", 365).max(0) as u64,
+            breach_db: Some(config::get_or("breach-db", String::new()))
+                .filter(|path| !path.is_empty())
+                .map(|path| get_absolute_path(&path)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditedEntry {
+    pub id: i32,
+    pub name: String,
+    pub login: String,
+}
+
+#[derive(Serialize)]
+pub struct WeakPassword {
+    pub entry: AuditedEntry,
+    pub score: u8,
+    pub warning: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct OldPassword {
+    pub entry: AuditedEntry,
+    pub days: Option<u64>, // None when the entry predates timestamps
+}
+
+#[derive(Serialize)]
+pub struct BreachedPassword {
+    pub entry: AuditedEntry,
+    pub count: u64, // times seen in breaches
+}
+
+#[derive(Serialize)]
+pub struct AuditReport {
+    pub reused: Vec<Vec<AuditedEntry>>, // groups of entries sharing a password
+    pub weak: Vec<WeakPassword>,
+    pub old: Vec<OldPassword>,
+    pub breached: Vec<BreachedPassword>,
+}
+
+impl AuditReport {
+    pub fn has_problems(&self) -> bool {
+        !self.reused.is_empty() || !self.weak.is_empty() || !self.old.is_empty() || !self.breached.is_empty()
+    }
+}
+
+fn audited(entry: &Entry) -> AuditedEntry {
+    AuditedEntry {
+        id: entry.id,
+        name: entry.name.clone(),
+        login: entry.login.clone(),
+    }
+}
+
+// decrypts every entry once. passwords only live in memory for the duration of the audit,
+// reuse is detected through keyed fingerprints that never leave this function
+pub fn audit(entries: &[Entry], key: &[u8], options: &AuditOptions) -> Result<AuditReport, Error> {
+    let mut report = AuditReport {
+        reused: Vec::new(),
+        weak: Vec::new(),
+        old: Vec::new(),
+        breached: Vec::new(),
+    };
+    let mut breach_db = match &options.breach_db {
+        Some(path) => Some(BreachDb::open(path)?),
+        None => None,
+    };
+    let mut groups: Vec<(Vec<u8>, Vec<&Entry>)> = Vec::new();
+    let now = unix_time();
+
+    // notes have no password to audit
+    for entry in entries.iter().filter(|entry| entry.kind == EntryKind::Password) {
+        let mut password = entry.get_password(key)?;
+        let fingerprint = fingerprint(&password, key);
+
+        // compare against every group so the time taken doesn't depend on where a match is
+        let mut group_index = None;
+        for (i, (group_fingerprint, _)) in groups.iter().enumerate() {
+            if bool::from(group_fingerprint.ct_eq(&fingerprint)) {
+                group_index = Some(i);
+            }
+        }
+        match group_index {
+            Some(i) => groups[i].1.push(entry),
+            None => groups.push((fingerprint, vec![entry])),
+        }
+
+        let estimate = strength::estimate(&password, &[&entry.name, &entry.login]);
+        let breach_count = match &mut breach_db {
+            Some(db) => db.count(&password),
+            None => Ok(None),
+        };
+        password.zeroize();
+
+        if let Some(count) = breach_count? {
+            report.breached.push(BreachedPassword {
+                entry: audited(entry),
+                count,
+            });
+        }
+
+        if estimate.score < options.min_score {
+            report.weak.push(WeakPassword {
+                entry: audited(entry),
+                score: estimate.score,
+                warning: estimate.warning,
+            });
+        }
+
+        // imported entries may only know when they were created, and entries from before
+        // timestamps were recorded know neither, so their age can't be vouched for
+        if options.max_age_days > 0 {
+            let days = entry
+                .password_changed_at
+                .or(entry.created_at)
+                .map(|changed_at| (now - changed_at).max(0) as u64 / (60 * 60 * 24));
+            if days.is_none_or(|days| days >= options.max_age_days) {
+                report.old.push(OldPassword {
+                    entry: audited(entry),
+                    days,
+                });
+            }
+        }
+    }
+
+    report.reused = groups
+        .into_iter()
+        .filter(|(_, entries)| entries.len() > 1)
+        .map(|(_, entries)| entries.iter().map(|entry| audited(entry)).collect())
+        .collect();
+
+    Ok(report)
+}
+
+// HMAC-SHA256 keyed with the data key, so fingerprints are useless without it
+fn fingerprint(password: &str, key: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+    mac.update(password.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
@@ -0,0 +1,391 @@
+use crate::{crypto, Error};
+use md4::Md4;
+use sha1::{Digest, Sha1};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// offline check against a downloaded Pwned Passwords file, ordered by hash:
+//   "<40 hex SHA-1 or 32 hex NTLM hash>:<count>" per line.
+// lookups binary search the file directly, or only the range of lines under the password's
+// hash prefix when an index built by `vodka breach-index` sits next to it (<file>.idx)
+
+const INDEX_MAGIC: &[u8; 8] = b"VODKAIX1";
+const INDEX_PREFIX_BITS: u32 = 20; // 5 hex characters, ~1M ranges
+const INDEX_HEADER_LENGTH: u64 = 24; // magic, hash length, size of the indexed file
+
+// below this many bytes the remaining range is scanned line by line
+const SCAN_THRESHOLD: u64 = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashKind {
+    Sha1,
+    Ntlm,
+}
+
+impl HashKind {
+    fn from_hex_length(length: usize) -> Option<HashKind> {
+        match length {
+            40 => Some(HashKind::Sha1),
+            32 => Some(HashKind::Ntlm),
+            _ => None,
+        }
+    }
+
+    fn hex_length(&self) -> usize {
+        match self {
+            HashKind::Sha1 => 40,
+            HashKind::Ntlm => 32,
+        }
+    }
+
+    // uppercase hex, as in the Pwned Passwords files
+    fn hash(&self, password: &str) -> String {
+        let digest = match self {
+            HashKind::Sha1 => Sha1::digest(password.as_bytes()).to_vec(),
+            HashKind::Ntlm => {
+                let utf16: Vec<u8> = password.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+                Md4::digest(&utf16).to_vec()
+            },
+        };
+
+        crypto::to_hex(&digest).to_uppercase()
+    }
+}
+
+pub struct BreachDb {
+    reader: BufReader<File>,
+    size: u64,
+    kind: HashKind,
+    index: Option<File>,
+}
+
+impl BreachDb {
+    pub fn open(path: &Path) -> Result<BreachDb, Error> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut first_line = String::new();
+        reader.read_line(&mut first_line)?;
+        let kind = parse_line(first_line.as_bytes())
+            .and_then(|(hash, _)| HashKind::from_hex_length(hash.len()))
+            .ok_or_else(|| Error::InvalidBreachDb(format!("{} is not a Pwned Passwords hash file", path.display())))?;
+
+        let index = open_index(&index_path(path), kind, size)?;
+
+        Ok(BreachDb {
+            reader,
+            size,
+            kind,
+            index,
+        })
+    }
+
+    pub fn kind(&self) -> HashKind {
+        self.kind
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.index.is_some()
+    }
+
+    // number of times the password was seen in breaches, None if it wasn't
+    pub fn count(&mut self, password: &str) -> Result<Option<u64>, Error> {
+        let target = self.kind.hash(password);
+
+        let (mut low, mut high) = match &mut self.index {
+            Some(index) => {
+                let prefix = u64::from_str_radix(&target[..(INDEX_PREFIX_BITS / 4) as usize], 16).unwrap();
+                index.seek(SeekFrom::Start(INDEX_HEADER_LENGTH + prefix * 8))?;
+
+                let mut offsets = [0u8; 16];
+                index.read_exact(&mut offsets)?;
+                (
+                    u64::from_le_bytes(offsets[..8].try_into().unwrap()),
+                    u64::from_le_bytes(offsets[8..].try_into().unwrap()),
+                )
+            },
+            None => (0, self.size),
+        };
+
+        // low is always the start of a line, high the start of a line or the end of the file
+        while high - low > SCAN_THRESHOLD {
+            let middle = low + (high - low) / 2;
+            self.reader.seek(SeekFrom::Start(middle))?;
+
+            let mut partial = Vec::new();
+            let line_start = middle + self.reader.read_until(b'\n', &mut partial)? as u64;
+            if line_start >= high {
+                break;
+            }
+
+            let mut line = Vec::new();
+            let line_end = line_start + self.reader.read_until(b'\n', &mut line)? as u64;
+
+            match parse_line(&line) {
+                Some((hash, count)) => match hash.as_str().cmp(target.as_str()) {
+                    std::cmp::Ordering::Equal => return Ok(Some(count)),
+                    std::cmp::Ordering::Less => low = line_end,
+                    std::cmp::Ordering::Greater => high = line_start,
+                },
+                None => return Err(Error::InvalidBreachDb(format!("malformed line at byte {}", line_start))),
+            }
+        }
+
+        self.reader.seek(SeekFrom::Start(low))?;
+        let mut remaining = Vec::new();
+        (&mut self.reader).take(high - low).read_to_end(&mut remaining)?;
+
+        for line in remaining.split(|&b| b == b'\n') {
+            if let Some((hash, count)) = parse_line(line) {
+                if hash == target {
+                    return Ok(Some(count));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut index_path = path.as_os_str().to_owned();
+    index_path.push(".idx");
+    PathBuf::from(index_path)
+}
+
+// an index that doesn't match the file (e.g. after downloading a newer one) is ignored
+fn open_index(path: &Path, kind: HashKind, size: u64) -> Result<Option<File>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut index = File::open(path)?;
+    let mut header = [0u8; INDEX_HEADER_LENGTH as usize];
+    index.read_exact(&mut header)?;
+
+    let expected_length = INDEX_HEADER_LENGTH + ((1u64 << INDEX_PREFIX_BITS) + 1) * 8;
+    let valid = &header[..8] == INDEX_MAGIC
+        && u64::from_le_bytes(header[8..16].try_into().unwrap()) == kind.hex_length() as u64
+        && u64::from_le_bytes(header[16..24].try_into().unwrap()) == size
+        && index.metadata()?.len() == expected_length;
+
+    if !valid {
+        eprintln!("Warning: ignoring {}, it doesn't match the breach file. Rebuild it with `vodka breach-index`", path.display());
+        return Ok(None);
+    }
+
+    Ok(Some(index))
+}
+
+// one pass over the file, recording where each hash prefix starts. returns the number of hashes
+pub fn build_index(path: &Path, output: &Path) -> Result<u64, Error> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut reader = BufReader::with_capacity(1 << 20, file);
+
+    let mut offsets: Vec<u64> = vec![0; (1 << INDEX_PREFIX_BITS) + 1];
+    let mut next_prefix = 0;
+    let mut offset = 0;
+    let mut count = 0;
+    let mut kind = None;
+    let mut previous_hash = String::new();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        let length = reader.read_until(b'\n', &mut line)? as u64;
+        if length == 0 {
+            break;
+        }
+
+        let (hash, _) = parse_line(&line)
+            .ok_or_else(|| Error::InvalidBreachDb(format!("malformed line at byte {}", offset)))?;
+
+        let line_kind = HashKind::from_hex_length(hash.len());
+        if line_kind.is_none() || (kind.is_some() && line_kind != kind) {
+            return Err(Error::InvalidBreachDb(format!("unexpected hash length at byte {}", offset)));
+        }
+        kind = line_kind;
+
+        if hash < previous_hash {
+            return Err(Error::InvalidBreachDb(String::from("the file is not ordered by hash, download the ordered-by-hash version")));
+        }
+
+        let prefix = usize::from_str_radix(&hash[..(INDEX_PREFIX_BITS / 4) as usize], 16).unwrap();
+        while next_prefix <= prefix {
+            offsets[next_prefix] = offset;
+            next_prefix += 1;
+        }
+
+        previous_hash = hash;
+        offset += length;
+        count += 1;
+    }
+
+    let kind = kind.ok_or_else(|| Error::InvalidBreachDb(String::from("the file is empty")))?;
+    while next_prefix < offsets.len() {
+        offsets[next_prefix] = size;
+        next_prefix += 1;
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    writer.write_all(INDEX_MAGIC)?;
+    writer.write_all(&(kind.hex_length() as u64).to_le_bytes())?;
+    writer.write_all(&size.to_le_bytes())?;
+    for offset in offsets {
+        writer.write_all(&offset.to_le_bytes())?;
+    }
+    writer.flush()?;
+
+    Ok(count)
+}
+
+// "HASH:COUNT", with or without a trailing \r\n
+fn parse_line(line: &[u8]) -> Option<(String, u64)> {
+    let line = std::str::from_utf8(line).ok()?.trim_end();
+    let (hash, count) = line.split_once(':')?;
+
+    if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some((hash.to_uppercase(), count.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    struct Fixture {
+        path: PathBuf,
+        hits: Vec<(String, u64)>, // passwords in the file with their count
+        before_first: String,
+        after_last: String,
+        between: String,
+    }
+
+    // a file of 500 hashes ordered by hash, leaving out the lowest, the highest and one in the middle
+    fn write_fixture(test: &str, kind: HashKind) -> Fixture {
+        let dir = std::env::temp_dir().join(format!("vodka-breach-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut passwords: Vec<(String, String)> = (0..503)
+            .map(|i| {
+                let password = format!("hunter{}", i);
+                (kind.hash(&password), password)
+            })
+            .collect();
+        passwords.sort();
+
+        let before_first = passwords.remove(0).1;
+        let after_last = passwords.pop().unwrap().1;
+        let between = passwords.remove(250).1;
+
+        let mut content = String::new();
+        let mut hits = Vec::new();
+        for (i, (hash, password)) in passwords.into_iter().enumerate() {
+            content.push_str(&format!("{}:{}\r\n", hash, i + 1));
+            hits.push((password, i as u64 + 1));
+        }
+
+        let path = dir.join("pwned-passwords.txt");
+        fs::write(&path, content).unwrap();
+
+        Fixture { path, hits, before_first, after_last, between }
+    }
+
+    fn check_lookups(fixture: &Fixture, indexed: bool) {
+        let mut db = BreachDb::open(&fixture.path).unwrap();
+        assert_eq!(db.is_indexed(), indexed);
+
+        // first line, last line and every one in between
+        for (password, count) in &fixture.hits {
+            assert_eq!(db.count(password).unwrap(), Some(*count), "{}", password);
+        }
+        assert_eq!(db.count(&fixture.before_first).unwrap(), None);
+        assert_eq!(db.count(&fixture.after_last).unwrap(), None);
+        assert_eq!(db.count(&fixture.between).unwrap(), None);
+    }
+
+    fn remove(fixture: Fixture) {
+        fs::remove_dir_all(fixture.path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn hashes_like_pwned_passwords() {
+        assert_eq!(HashKind::Sha1.hash("password"), "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8");
+        assert_eq!(HashKind::Ntlm.hash("password"), "8846F7EAEE8FB117AD06BDD830B7586C");
+    }
+
+    #[test]
+    fn looks_up_sha1_files() {
+        let fixture = write_fixture("sha1", HashKind::Sha1);
+        assert_eq!(BreachDb::open(&fixture.path).unwrap().kind(), HashKind::Sha1);
+        check_lookups(&fixture, false);
+
+        assert_eq!(build_index(&fixture.path, &index_path(&fixture.path)).unwrap(), 500);
+        check_lookups(&fixture, true);
+
+        remove(fixture);
+    }
+
+    #[test]
+    fn looks_up_ntlm_files() {
+        let fixture = write_fixture("ntlm", HashKind::Ntlm);
+        assert_eq!(BreachDb::open(&fixture.path).unwrap().kind(), HashKind::Ntlm);
+        check_lookups(&fixture, false);
+
+        build_index(&fixture.path, &index_path(&fixture.path)).unwrap();
+        check_lookups(&fixture, true);
+
+        remove(fixture);
+    }
+
+    #[test]
+    fn ignores_stale_and_mismatched_indexes() {
+        let fixture = write_fixture("stale", HashKind::Sha1);
+        build_index(&fixture.path, &index_path(&fixture.path)).unwrap();
+
+        // the file changed after the index was built
+        let mut content = fs::read_to_string(&fixture.path).unwrap();
+        content.push_str("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:1\r\n");
+        fs::write(&fixture.path, &content).unwrap();
+        check_lookups(&fixture, false);
+
+        // an index of an NTLM file of the same size
+        let ntlm = write_fixture("stale-ntlm", HashKind::Ntlm);
+        build_index(&ntlm.path, &index_path(&ntlm.path)).unwrap();
+        fs::copy(index_path(&ntlm.path), index_path(&fixture.path)).unwrap();
+        let size = fs::metadata(&fixture.path).unwrap().len();
+        let mut header = fs::read(index_path(&fixture.path)).unwrap();
+        header[16..24].copy_from_slice(&size.to_le_bytes());
+        fs::write(index_path(&fixture.path), header).unwrap();
+        check_lookups(&fixture, false);
+
+        // a truncated index
+        build_index(&fixture.path, &index_path(&fixture.path)).unwrap();
+        let index = fs::read(index_path(&fixture.path)).unwrap();
+        fs::write(index_path(&fixture.path), &index[..index.len() - 8]).unwrap();
+        check_lookups(&fixture, false);
+
+        remove(ntlm);
+        remove(fixture);
+    }
+
+    #[test]
+    fn refuses_unordered_files() {
+        let fixture = write_fixture("unordered", HashKind::Sha1);
+        let content = fs::read_to_string(&fixture.path).unwrap();
+        let mut lines: Vec<&str> = content.lines().collect();
+        lines.swap(10, 20);
+        fs::write(&fixture.path, lines.join("\n")).unwrap();
+
+        assert!(matches!(build_index(&fixture.path, &index_path(&fixture.path)), Err(Error::InvalidBreachDb(_))));
+
+        remove(fixture);
+    }
+}
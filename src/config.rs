@@ -48,6 +48,11 @@ pub fn parse(s: &str) -> Value {
 pub fn create_default_config() -> Result<(), Error> {
     let default = r#"
         default-cmd = "help"
+        clipboard-timeout = 30
+        agent-timeout = 900
+        ssh-agent-timeout = 900
+        breach-db = ""
+        attachment-max-size = 10485760
         hash-memory = 19456
         hash-iterations = 2
         hash-parallelism = 1
@@ -58,6 +63,38 @@ pub fn create_default_config() -> Result<(), Error> {
         delete = true
         list = true
         config = false
+        
+        [strength]
+        warn-below = 3
+        refuse-below = 0
+        master-key-warn-below = 3
+        master-key-refuse-below = 2
+        
+        [audit]
+        min-score = 3
+        max-age-days = 365
+        
+        [passphrase]
+        words = 6
+        separator = "-"
+        capitalize = "lower"
+        with-digit = false
+        with-symbol = false
+        wordlist = ""
+        
+        [generator]
+        length = 24
+        lowercase = true
+        uppercase = true
+        digits = true
+        symbols = true
+        min-lowercase = 1
+        min-uppercase = 1
+        min-digits = 1
+        min-symbols = 1
+        symbol-set = "!@#$%^&*()-_+=[]{}|;:,.<>?"
+        exclude-similar = false
+        no-repeat = false
     "#
         .lines()
         .map(|line| line.trim_start())
@@ -69,16 +106,16 @@ pub fn create_default_config() -> Result<(), Error> {
     write_to_file("config.toml", default, true)
 }
 
+// empty (so every setting has its default) until setup creates config.toml
 pub fn config_str() -> String {
-    read_file("config.toml").unwrap()
+    read_file("config.toml").unwrap_or_default()
 }
 
 fn config() -> Table {
     config_str().parse::<Table>().unwrap()
 }
 
-// returns value as str regardless of toml type
-pub fn get_as_str(path: &str) -> Option<String> {
+fn get_value(path: &str) -> Option<Value> {
     let split_path: Vec<String> = path
         .split(".")
         .map(|s| s.to_string())
@@ -100,22 +137,19 @@ pub fn get_as_str(path: &str) -> Option<String> {
         }
     }
 
-    if let Some(v) = current_value {
-        Some(v.to_string())
-    } else {
-        None
-    }
+    current_value.cloned()
+}
+
+// returns value as str regardless of toml type
+pub fn get_as_str(path: &str) -> Option<String> {
+    get_value(path).map(|v| v.to_string())
 }
 
 pub fn get<T>(path: &str) -> Option<T> 
 where
     T: FromValue
 {
-    if let Some(val) = get_as_str(path) {
-        return Some(T::from_value(&parse(&val)));
-    }
-    
-    None
+    get_value(path).map(|v| T::from_value(&v))
 }
 
 pub fn get_or<T>(path: &str, default: T) -> T 
@@ -1,13 +1,16 @@
 use aes::Aes256;
-use aes::cipher::{BlockEncrypt, BlockDecrypt, KeyInit};
-use aes::cipher::generic_array::GenericArray;
+use aes::cipher::BlockDecrypt;
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, Payload};
+use aes_gcm::aead::generic_array::GenericArray;
 use argon2::{Algorithm, Argon2, Params, Version};
 use argon2::password_hash::{
     PasswordHash, PasswordHasher, PasswordVerifier, SaltString
 };
-use crate::{config, store};
-use rand_core::OsRng;
-use rand::{Rng, prelude::SliceRandom};
+use crate::{config, store, Error};
+use crate::generator::PasswordSpec;
+use rand_core::{OsRng, RngCore};
+use rand::Rng;
 use sha2::{Sha256, Digest};
 
 fn get_argon2_instance() -> Argon2<'static> {
@@ -38,6 +41,51 @@ pub fn hash_argon2(password: &[u8]) -> Option<String> {
     None
 }
 
+// PHC-style description of how the vault key is derived, without any hash output.
+// e.g. $argon2id$v=19$m=19456,t=2,p=1$<salt>. the salt is independent of the one in .master_key
+pub fn new_kdf() -> String {
+    let params = get_argon2_instance().params().clone();
+    let salt = SaltString::generate(&mut OsRng);
+
+    format!(
+        "${}$v=19$m={},t={},p={}${}",
+        Algorithm::Argon2id.ident(),
+        params.m_cost(),
+        params.t_cost(),
+        params.p_cost(),
+        salt.as_str()
+    )
+}
+
+// random 256-bit key for encrypting the entries of a vault
+pub fn generate_key() -> Vec<u8> {
+    let mut key = vec![0u8; 32];
+    OsRng.fill_bytes(&mut key);
+
+    key
+}
+
+// derives the 256-bit key wrapping the data key from the master key, with the parameters stored in the cellar
+pub fn derive_key(master_key: &[u8], kdf: &str) -> Result<Vec<u8>, Error> {
+    let parsed = PasswordHash::new(kdf).map_err(|_| Error::InvalidKdf)?;
+    let algorithm = Algorithm::try_from(parsed.algorithm).map_err(|_| Error::InvalidKdf)?;
+    let params = Params::try_from(&parsed).map_err(|_| Error::InvalidKdf)?;
+
+    let mut salt_buffer = [0u8; 64];
+    let salt = parsed.salt
+        .ok_or(Error::InvalidKdf)?
+        .decode_b64(&mut salt_buffer)
+        .map_err(|_| Error::InvalidKdf)?;
+
+    let mut key = vec![0u8; 32];
+    Argon2::new(algorithm, Version::V0x13, params)
+        .hash_password_into(master_key, salt, &mut key)
+        .map_err(|_| Error::InvalidKdf)?;
+
+    Ok(key)
+}
+
+// only used to derive the key of cellars from before the vault key was salted
 pub fn hash_sha256(password: &[u8]) -> Vec<u8> {
     let mut sha256 = Sha256::new();
     
@@ -46,6 +94,21 @@ pub fn hash_sha256(password: &[u8]) -> Vec<u8> {
     sha256.finalize().to_vec()
 }
 
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
 pub fn verify_password(password: &[u8]) -> Option<bool> {
     let hash = match store::read_file(".master_key") {
         Ok(hash) => hash.to_string(),
@@ -61,27 +124,55 @@ pub fn verify_password(password: &[u8]) -> Option<bool> {
     None
 }
 
-pub fn encrypt_aes256(data: &[u8], key: &[u8]) -> Vec<u8> {
+// current format of encrypted blobs: version byte, 12 byte nonce, ciphertext + GCM tag
+const BLOB_VERSION: u8 = 1;
+const NONCE_LENGTH: usize = 12;
+
+// AES-256-GCM with a random nonce for every call, so identical passwords never share a ciphertext
+pub fn encrypt(data: &[u8], key: &[u8]) -> Vec<u8> {
+    encrypt_with_aad(data, key, &[])
+}
+
+// fails on a wrong key, a tampered/corrupted blob or an unknown blob version
+pub fn decrypt(blob: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    decrypt_with_aad(blob, key, &[])
+}
+
+// like encrypt, but the blob only decrypts with the same associated data. binds a blob to
+// where it belongs, e.g. a chunk of an attachment to its position
+pub fn encrypt_with_aad(data: &[u8], key: &[u8], aad: &[u8]) -> Vec<u8> {
     assert_eq!(key.len(), 32, "Key length must be 32 bytes (256 bits)");
 
-    let cipher = Aes256::new(GenericArray::from_slice(key));
+    let cipher = Aes256Gcm::new(GenericArray::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, Payload { msg: data, aad }).expect("Failed to encrypt");
 
-    let mut padded_data = data.to_vec();
-    let padding_length = 16 - (data.len() % 16);
-    padded_data.extend(vec![padding_length as u8; padding_length]);
+    let mut blob = Vec::with_capacity(1 + NONCE_LENGTH + ciphertext.len());
+    blob.push(BLOB_VERSION);
+    blob.extend_from_slice(nonce.as_slice());
+    blob.extend_from_slice(&ciphertext);
 
-    let mut encrypted_data = Vec::new();
-    for chunk in padded_data.chunks_exact(16) {
-        let mut block = GenericArray::clone_from_slice(chunk);
+    blob
+}
 
-        cipher.encrypt_block(&mut block);
-        encrypted_data.extend_from_slice(block.as_slice());
+pub fn decrypt_with_aad(blob: &[u8], key: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+    assert_eq!(key.len(), 32, "Key length must be 32 bytes (256 bits)");
+
+    if blob.len() < 1 + NONCE_LENGTH || blob[0] != BLOB_VERSION {
+        return Err(Error::DecryptionFailed);
     }
 
-    encrypted_data
+    let cipher = Aes256Gcm::new(GenericArray::from_slice(key));
+    let nonce = GenericArray::from_slice(&blob[1..1 + NONCE_LENGTH]);
+
+    cipher
+        .decrypt(nonce, Payload { msg: &blob[1 + NONCE_LENGTH..], aad })
+        .map_err(|_| Error::DecryptionFailed)
 }
 
-pub fn decrypt_aes256(encrypted_data: &[u8], key: &[u8]) -> Vec<u8> {
+// block-by-block AES-256 used by cellars before blobs were versioned.
+// only kept around to migrate those cellars, never encrypt with it
+pub fn decrypt_legacy_aes256(encrypted_data: &[u8], key: &[u8]) -> Vec<u8> {
     assert_eq!(key.len(), 32, "Key length must be 32 bytes (256 bits)");
 
     let cipher = Aes256::new(GenericArray::from_slice(key));
@@ -104,34 +195,18 @@ pub fn decrypt_aes256(encrypted_data: &[u8], key: &[u8]) -> Vec<u8> {
     decrypted_data
 }
 
-fn choose_random(chars: &Vec<char>) -> char {
-    chars[OsRng.gen_range(0..chars.len())]
+// short random alphanumeric string, e.g. for temporary file names
+pub fn get_random_suffix() -> String {
+    let charset: Vec<char> = ('a'..='z').chain('0'..='9').collect();
+    (0..12).map(|_| choose_random(&charset)).collect()
 }
 
-// ensures at least 1 special char, number, capital letter
-// length 24
-pub fn get_random_password() -> String {
-    let mut password: Vec<char> = Vec::new();
-    
-    let mut charset: Vec<char> = ('A'..='Z')
-        .chain('a'..='z')
-        .chain('0'..='9')
-        .collect();
-    let special_chars: Vec<char> = "!@#$%^&*()-_+=[]{}|;:,.<>?".chars().collect();
-    charset.extend(&special_chars);
-    
-    password.push(OsRng.gen_range('A'..='Z'));
-    password.push(OsRng.gen_range('a'..='z'));
-    password.push(OsRng.gen_range('0'..='9'));
-    password.push(choose_random(&special_chars));
-
-    while password.len() < 24 {
-        password.push(choose_random(&charset)); // clone
-    }
-
-    let mut indices: Vec<usize> = (0..password.len()).collect();
-    indices.shuffle(&mut OsRng);
-    password = indices.iter().map(|&i| password[i]).collect();
+fn choose_random(chars: &[char]) -> char {
+    chars[OsRng.gen_range(0..chars.len())]
+}
 
-    password.into_iter().collect()
-}
\ No newline at end of file
+// follows the [generator] defaults of config.toml. without them: length 24,
+// at least 1 special char, number, capital letter
+pub fn get_random_password() -> Result<String, Error> {
+    PasswordSpec::from_config().generate()
+}
@@ -1,34 +1,320 @@
 use cli_table::{print_stdout, Cell, CellStruct, Style, Table};
-use crate::Entry;
+use crate::{ssh, Attachment, Entry, Field, PasswordVersion, SshKey};
+use crate::audit::{AuditReport, AuditedEntry};
 use crate::store::{get_all_rows, search_entries};
+use std::collections::BTreeMap;
 
-pub fn display_all() -> bool {
+pub fn display_all(show_timestamps: bool) -> bool {
     let entries = get_all_rows();
-    display(entries)
+    display(entries, show_timestamps)
 }
 
-pub fn display_search(name: String, login: String) -> bool {
+pub fn display_search(name: String, login: String, show_timestamps: bool) -> bool {
     let entries = search_entries(name, login);
-    display(entries)
+    display(entries, show_timestamps)
 }
 
-pub fn display(entries: Vec<Entry>) -> bool {
+pub fn display(entries: Vec<Entry>, show_timestamps: bool) -> bool {
     let mut table: Vec<Vec<CellStruct>> = Vec::new();
 
     for entry in entries {
-        table.push(entry.as_table_row())
+        let mut row = entry.as_table_row();
+        if show_timestamps {
+            row.extend([
+                format_time(entry.created_at).cell(),
+                format_time(entry.updated_at).cell(),
+                format_time(entry.password_changed_at).cell(),
+                match entry.last_used_at {
+                    Some(_) => format_time(entry.last_used_at),
+                    None => String::from("never"),
+                }.cell(),
+            ]);
+        }
+        table.push(row)
+    }
+
+    let mut title = vec![
+        "ID".cell().bold(true),
+        "Type".cell().bold(true),
+        "Name".cell().bold(true),
+        "Login".cell().bold(true),
+        "Password".cell().bold(true),
+        "Comment".cell().bold(true),
+        "Folder".cell().bold(true),
+        "Tags".cell().bold(true),
+        "URLs".cell().bold(true),
+    ];
+    if show_timestamps {
+        title.extend([
+            "Created".cell().bold(true),
+            "Updated".cell().bold(true),
+            "Password changed".cell().bold(true),
+            "Last used".cell().bold(true),
+        ]);
+    }
+
+    let table = table
+        .table()
+        .title(title)
+        .bold(true);
+
+    print_stdout(table).is_ok()
+}
+
+// entries grouped by folder, subfolders first:
+//   work/
+//   └── aws/
+//       └── bob@console (3) [prod]
+pub fn display_tree(entries: Vec<Entry>) {
+    #[derive(Default)]
+    struct Folder {
+        subfolders: BTreeMap<String, Folder>,
+        entries: Vec<Entry>,
+    }
+
+    let mut root = Folder::default();
+    for entry in entries {
+        let mut folder = &mut root;
+        for part in entry.folder.split('/').filter(|part| !part.is_empty()) {
+            folder = folder.subfolders.entry(part.to_string()).or_default();
+        }
+        folder.entries.push(entry);
+    }
+
+    fn print_folder(folder: &Folder, prefix: &str) {
+        let count = folder.subfolders.len() + folder.entries.len();
+        let lines = folder.subfolders
+            .iter()
+            .map(|(name, subfolder)| (format!("{}/", name), Some(subfolder)))
+            .chain(folder.entries.iter().map(|entry| (tree_label(entry), None)));
+
+        for (i, (label, subfolder)) in lines.enumerate() {
+            let last = i + 1 == count;
+            println!("{}{}{}", prefix, if last { "└── " } else { "├── " }, label);
+
+            if let Some(subfolder) = subfolder {
+                print_folder(subfolder, &format!("{}{}", prefix, if last { "    " } else { "│   " }));
+            }
+        }
+    }
+
+    // entries without a folder are printed at the top level, without branches
+    for (name, subfolder) in &root.subfolders {
+        println!("{}/", name);
+        print_folder(subfolder, "");
+    }
+    for entry in &root.entries {
+        println!("{}", tree_label(entry));
+    }
+}
+
+fn tree_label(entry: &Entry) -> String {
+    let mut label = if entry.login.is_empty() {
+        format!("{} ({})", entry.name, entry.id)
+    } else {
+        format!("{}@{} ({})", entry.login, entry.name, entry.id)
+    };
+
+    if !entry.tags.is_empty() {
+        label.push_str(&format!(" [{}]", entry.tags.join(", ")));
+    }
+
+    label
+}
+
+pub fn display_tags(tags: &[(String, usize)]) -> bool {
+    let table = tags
+        .iter()
+        .map(|(name, count)| vec![name.cell(), count.cell()])
+        .collect::<Vec<Vec<CellStruct>>>()
+        .table()
+        .title(vec![
+            "Tag".cell().bold(true),
+            "Entries".cell().bold(true),
+        ])
+        .bold(true);
+
+    print_stdout(table).is_ok()
+}
+
+// one row per problem, reused passwords are numbered by group
+pub fn display_audit(report: &AuditReport) -> bool {
+    let row = |problem: String, entry: &AuditedEntry, details: String| vec![
+        problem.cell(),
+        entry.id.cell(),
+        (&entry.name).cell(),
+        (&entry.login).cell(),
+        details.cell(),
+    ];
+
+    let mut table: Vec<Vec<CellStruct>> = Vec::new();
+
+    for (i, group) in report.reused.iter().enumerate() {
+        for entry in group {
+            table.push(row(
+                String::from("Reused"),
+                entry,
+                format!("group {}, shared by {} entries", i + 1, group.len())
+            ));
+        }
+    }
+
+    for weak in &report.weak {
+        let mut details = format!("score {}/4", weak.score);
+        if let Some(warning) = &weak.warning {
+            details.push_str(&format!(": {}", warning));
+        }
+        table.push(row(String::from("Weak"), &weak.entry, details));
+    }
+
+    for breached in &report.breached {
+        table.push(row(String::from("Breached"), &breached.entry, format!("seen {} times in breaches", breached.count)));
+    }
+
+    for old in &report.old {
+        let age = match old.days {
+            Some(days) => format!("changed {} days ago", days),
+            None => String::from("never recorded when it was changed"),
+        };
+        table.push(row(String::from("Old"), &old.entry, age));
     }
 
     let table = table
         .table()
         .title(vec![
+            "Problem".cell().bold(true),
             "ID".cell().bold(true),
             "Name".cell().bold(true),
             "Login".cell().bold(true),
-            "Password".cell().bold(true),
-            "Comment".cell().bold(true),
+            "Details".cell().bold(true),
+        ])
+        .bold(true);
+
+    print_stdout(table).is_ok()
+}
+
+pub fn display_history(versions: &[PasswordVersion]) -> bool {
+    let table = versions
+        .iter()
+        .map(|version| vec![
+            version.version.cell(),
+            format_time(version.set_at).cell(),
+            match version.replaced_at {
+                Some(replaced_at) => format_time(Some(replaced_at)),
+                None => String::from("(current)"),
+            }.cell(),
+        ])
+        .collect::<Vec<Vec<CellStruct>>>()
+        .table()
+        .title(vec![
+            "Version".cell().bold(true),
+            "Set".cell().bold(true),
+            "Replaced".cell().bold(true),
+        ])
+        .bold(true);
+
+    print_stdout(table).is_ok()
+}
+
+// secret values are masked like passwords
+pub fn display_fields(fields: &[Field]) -> bool {
+    let table = fields
+        .iter()
+        .map(|field| vec![
+            (&field.name).cell(),
+            if field.secret { String::from("********") } else { String::from_utf8_lossy(&field.value).to_string() }.cell(),
+        ])
+        .collect::<Vec<Vec<CellStruct>>>()
+        .table()
+        .title(vec![
+            "Field".cell().bold(true),
+            "Value".cell().bold(true),
+        ])
+        .bold(true);
+
+    print_stdout(table).is_ok()
+}
+
+pub fn display_attachments(attachments: &[Attachment]) -> bool {
+    let table = attachments
+        .iter()
+        .map(|attachment| vec![
+            (&attachment.name).cell(),
+            format_size(attachment.size).cell(),
+            format_time(attachment.created_at).cell(),
+        ])
+        .collect::<Vec<Vec<CellStruct>>>()
+        .table()
+        .title(vec![
+            "Attachment".cell().bold(true),
+            "Size".cell().bold(true),
+            "Added".cell().bold(true),
+        ])
+        .bold(true);
+
+    print_stdout(table).is_ok()
+}
+
+pub fn display_ssh_keys(keys: &[SshKey]) -> bool {
+    let table = keys
+        .iter()
+        .map(|key| vec![
+            key.entry_id.cell(),
+            (&key.name).cell(),
+            ssh::algorithm(&key.public_key).unwrap_or_default().cell(),
+            ssh::fingerprint(&key.public_key).unwrap_or_default().cell(),
+            if key.confirm { "yes" } else { "no" }.cell(),
+        ])
+        .collect::<Vec<Vec<CellStruct>>>()
+        .table()
+        .title(vec![
+            "ID".cell().bold(true),
+            "Name".cell().bold(true),
+            "Type".cell().bold(true),
+            "Fingerprint".cell().bold(true),
+            "Confirm".cell().bold(true),
         ])
         .bold(true);
 
     print_stdout(table).is_ok()
-}
\ No newline at end of file
+}
+
+// bytes as "512 B", "1.5 KiB", "12.0 MiB"...
+pub fn format_size(size: u64) -> String {
+    let units = ["KiB", "MiB", "GiB"];
+
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, units[unit])
+}
+
+// unix time as "YYYY-MM-DD HH:MM" (UTC)
+pub fn format_time(time: Option<i64>) -> String {
+    let time = match time {
+        Some(time) => time,
+        None => return String::from("unknown"),
+    };
+
+    // days since 1970-01-01 to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days = time.div_euclid(86400) + 719468;
+    let seconds = time.rem_euclid(86400);
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds / 3600, seconds % 3600 / 60)
+}
@@ -0,0 +1,315 @@
+use crate::{config, get_absolute_path, Error};
+use rand::{Rng, prelude::SliceRandom};
+use rand_core::OsRng;
+use std::path::PathBuf;
+
+pub const DEFAULT_SYMBOLS: &str = "!@#$%^&*()-_+=[]{}|;:,.<>?";
+
+// characters that are easy to mix up when reading a password
+pub const SIMILAR_CHARS: &str = "l1I0O";
+
+// a character class of a PasswordSpec. `min` characters of an enabled class are guaranteed
+#[derive(Clone, Debug)]
+pub struct CharClass {
+    pub enabled: bool,
+    pub min: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct PasswordSpec {
+    pub length: usize,
+    pub lowercase: CharClass,
+    pub uppercase: CharClass,
+    pub digits: CharClass,
+    pub symbols: CharClass,
+    pub symbol_set: String,
+    pub exclude_similar: bool,
+    pub no_repeat: bool, // every character at most once
+}
+
+impl Default for PasswordSpec {
+    fn default() -> PasswordSpec {
+        PasswordSpec {
+            length: 24,
+            lowercase: CharClass { enabled: true, min: 1 },
+            uppercase: CharClass { enabled: true, min: 1 },
+            digits: CharClass { enabled: true, min: 1 },
+            symbols: CharClass { enabled: true, min: 1 },
+            symbol_set: String::from(DEFAULT_SYMBOLS),
+            exclude_similar: false,
+            no_repeat: false,
+        }
+    }
+}
+
+impl PasswordSpec {
+    // defaults from the [generator] table of config.toml
+    pub fn from_config() -> PasswordSpec {
+        let default = PasswordSpec::default();
+        let class = |name: &str, default: &CharClass| CharClass {
+            enabled: config::get_or(&format!("generator.{}", name), default.enabled),
+            min: get_count(&format!("generator.min-{}", name), default.min),
+        };
+
+        PasswordSpec {
+            length: get_count("generator.length", default.length),
+            lowercase: class("lowercase", &default.lowercase),
+            uppercase: class("uppercase", &default.uppercase),
+            digits: class("digits", &default.digits),
+            symbols: class("symbols", &default.symbols),
+            symbol_set: config::get_or("generator.symbol-set", default.symbol_set),
+            exclude_similar: config::get_or("generator.exclude-similar", default.exclude_similar),
+            no_repeat: config::get_or("generator.no-repeat", default.no_repeat),
+        }
+    }
+
+    // characters of each enabled class, after exclusions
+    fn charsets(&self) -> Vec<(&'static str, &CharClass, Vec<char>)> {
+        let classes: [(&'static str, &CharClass, Vec<char>); 4] = [
+            ("lowercase", &self.lowercase, ('a'..='z').collect()),
+            ("uppercase", &self.uppercase, ('A'..='Z').collect()),
+            ("digits", &self.digits, ('0'..='9').collect()),
+            // letters and digits in a custom symbol set would overlap the other classes
+            ("symbols", &self.symbols, self.symbol_set.chars().filter(|c| !c.is_ascii_alphanumeric() && !c.is_whitespace()).collect()),
+        ];
+
+        classes
+            .into_iter()
+            .filter(|(_, class, _)| class.enabled)
+            .map(|(name, class, mut chars)| {
+                if self.exclude_similar {
+                    chars.retain(|c| !SIMILAR_CHARS.contains(*c));
+                }
+                chars.sort_unstable();
+                chars.dedup();
+                (name, class, chars)
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let charsets = self.charsets();
+
+        if charsets.is_empty() {
+            return Err(Error::InvalidPasswordSpec(String::from("no character classes enabled")));
+        }
+
+        let mut pool_size = 0;
+        let mut min_total = 0;
+
+        for (name, class, chars) in &charsets {
+            if chars.is_empty() {
+                return Err(Error::InvalidPasswordSpec(format!("no {} characters left to choose from", name)));
+            }
+            if self.no_repeat && class.min > chars.len() {
+                return Err(Error::InvalidPasswordSpec(format!("not enough {} characters for --no-repeat", name)));
+            }
+
+            pool_size += chars.len();
+            min_total += class.min;
+        }
+
+        if self.length == 0 {
+            return Err(Error::InvalidPasswordSpec(String::from("length must be positive")));
+        }
+        if min_total > self.length {
+            return Err(Error::InvalidPasswordSpec(format!("minimums add up to {}, more than the length {}", min_total, self.length)));
+        }
+        if self.no_repeat && self.length > pool_size {
+            return Err(Error::InvalidPasswordSpec(format!("only {} distinct characters for --no-repeat", pool_size)));
+        }
+
+        Ok(())
+    }
+
+    pub fn generate(&self) -> Result<String, Error> {
+        self.validate()?;
+
+        let charsets = self.charsets();
+        let mut password: Vec<char> = Vec::with_capacity(self.length);
+
+        // guaranteed characters of each class first
+        for (_, class, chars) in &charsets {
+            for _ in 0..class.min {
+                password.push(self.choose(chars, &password));
+            }
+        }
+
+        let pool: Vec<char> = charsets.iter().flat_map(|(_, _, chars)| chars.clone()).collect();
+        while password.len() < self.length {
+            password.push(self.choose(&pool, &password));
+        }
+
+        password.shuffle(&mut OsRng);
+
+        Ok(password.into_iter().collect())
+    }
+
+    fn choose(&self, chars: &[char], used: &[char]) -> char {
+        if self.no_repeat {
+            let unused: Vec<char> = chars.iter().filter(|c| !used.contains(c)).copied().collect();
+            return unused[OsRng.gen_range(0..unused.len())];
+        }
+
+        chars[OsRng.gen_range(0..chars.len())]
+    }
+}
+
+fn get_count(path: &str, default: usize) -> usize {
+    config::get_or(path, default as i32)
+        .try_into()
+        .unwrap_or_else(|_| panic!("Expected positive integer value for {}", path))
+}
+
+// default wordlist for passphrases: the EFF large wordlist, 7776 words (about 12.9 bits per word).
+// it keeps the dice column, load_wordlist strips it
+// other languages/lists can be used with --wordlist or passphrase.wordlist in config.toml
+pub(crate) const DEFAULT_WORDLIST: &str = include_str!("../wordlists/eff_large_wordlist.txt");
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capitalization {
+    Lower,
+    Upper,
+    Capitalize, // first letter of each word
+    Random, // each word either lowercase or capitalized, adds 1 bit per word
+}
+
+impl Capitalization {
+    pub fn parse(s: &str) -> Result<Capitalization, Error> {
+        match s {
+            "lower" => Ok(Capitalization::Lower),
+            "upper" => Ok(Capitalization::Upper),
+            "capitalize" => Ok(Capitalization::Capitalize),
+            "random" => Ok(Capitalization::Random),
+            _ => Err(Error::InvalidPasswordSpec(format!("unknown capitalization '{}'", s))),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PassphraseSpec {
+    pub words: usize,
+    pub separator: String,
+    pub capitalization: Capitalization,
+    pub with_digit: bool, // a random digit after a random word
+    pub with_symbol: bool, // a random symbol after a random word
+    pub symbol_set: String, // what with_symbol picks from, generator.symbol-set in config.toml
+    pub wordlist: Option<PathBuf>, // embedded list if None
+}
+
+pub struct Passphrase {
+    pub passphrase: String,
+    pub entropy: f64, // bits, assuming the attacker knows the spec and the wordlist
+}
+
+impl Default for PassphraseSpec {
+    fn default() -> PassphraseSpec {
+        PassphraseSpec {
+            words: 6,
+            separator: String::from("-"),
+            capitalization: Capitalization::Lower,
+            with_digit: false,
+            with_symbol: false,
+            symbol_set: String::from(DEFAULT_SYMBOLS),
+            wordlist: None,
+        }
+    }
+}
+
+impl PassphraseSpec {
+    // defaults from the [passphrase] table of config.toml
+    pub fn from_config() -> Result<PassphraseSpec, Error> {
+        let default = PassphraseSpec::default();
+        let wordlist: String = config::get_or("passphrase.wordlist", String::new());
+
+        Ok(PassphraseSpec {
+            words: get_count("passphrase.words", default.words),
+            separator: config::get_or("passphrase.separator", default.separator),
+            capitalization: Capitalization::parse(&config::get_or("passphrase.capitalize", String::from("lower")))?,
+            with_digit: config::get_or("passphrase.with-digit", default.with_digit),
+            with_symbol: config::get_or("passphrase.with-symbol", default.with_symbol),
+            symbol_set: config::get_or("generator.symbol-set", default.symbol_set),
+            wordlist: if wordlist.is_empty() { None } else { Some(PathBuf::from(wordlist)) },
+        })
+    }
+
+    pub fn generate(&self) -> Result<Passphrase, Error> {
+        let wordlist = match &self.wordlist {
+            Some(path) => load_wordlist(&std::fs::read_to_string(get_absolute_path(&path.to_string_lossy()))?)?,
+            None => load_wordlist(DEFAULT_WORDLIST)?,
+        };
+
+        if self.words == 0 {
+            return Err(Error::InvalidPasswordSpec(String::from("word count must be positive")));
+        }
+
+        let mut entropy = self.words as f64 * (wordlist.len() as f64).log2();
+        let mut words: Vec<String> = Vec::with_capacity(self.words);
+
+        for _ in 0..self.words {
+            let word = &wordlist[OsRng.gen_range(0..wordlist.len())];
+
+            words.push(match self.capitalization {
+                Capitalization::Lower => word.to_lowercase(),
+                Capitalization::Upper => word.to_uppercase(),
+                Capitalization::Capitalize => capitalize(word),
+                Capitalization::Random => {
+                    if OsRng.gen_bool(0.5) { capitalize(word) } else { word.to_lowercase() }
+                },
+            });
+        }
+
+        if self.capitalization == Capitalization::Random {
+            entropy += self.words as f64;
+        }
+
+        if self.with_digit {
+            let index = OsRng.gen_range(0..words.len());
+            words[index].push(OsRng.gen_range('0'..='9'));
+            entropy += (10.0 * words.len() as f64).log2();
+        }
+
+        if self.with_symbol {
+            let symbols: Vec<char> = self.symbol_set.chars().filter(|c| !c.is_ascii_alphanumeric() && !c.is_whitespace()).collect();
+            if symbols.is_empty() {
+                return Err(Error::InvalidPasswordSpec(String::from("symbol set has no symbols")));
+            }
+            let index = OsRng.gen_range(0..words.len());
+            words[index].push(symbols[OsRng.gen_range(0..symbols.len())]);
+            entropy += (symbols.len() as f64 * words.len() as f64).log2();
+        }
+
+        Ok(Passphrase {
+            passphrase: words.join(&self.separator),
+            entropy,
+        })
+    }
+}
+
+// one word per line. diceware style lines ("11111\tabacus") are accepted too, so the EFF lists work as is
+fn load_wordlist(content: &str) -> Result<Vec<String>, Error> {
+    let mut words: Vec<String> = content
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .map(|word| word.to_string())
+        .collect();
+
+    words.sort_unstable();
+    words.dedup();
+
+    // a short list would make for a guessable passphrase
+    if words.len() < 1024 {
+        return Err(Error::InvalidPasswordSpec(format!("wordlist has {} distinct words, at least 1024 are needed", words.len())));
+    }
+
+    Ok(words)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(|c| c.to_lowercase())).collect(),
+        None => String::new(),
+    }
+}
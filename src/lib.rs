@@ -1,25 +1,80 @@
 use arboard::Clipboard;
 use cli_table::{Cell, CellStruct};
+use otp::Otp;
 use rpassword::prompt_password;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::io::{Read, Write};
 use std::path::PathBuf;
 
+#[cfg(unix)]
+pub mod agent;
+pub mod attachments;
+pub mod audit;
+pub mod breach;
 pub mod config;
 pub mod crypto;
 pub mod display;
+pub mod generator;
+pub mod migrations;
+pub mod otp;
+pub mod picker;
+pub mod search;
 pub mod setup;
+pub mod ssh;
+#[cfg(unix)]
+pub mod ssh_agent;
 pub mod store;
+pub mod strength;
 pub mod transport;
+pub mod urls;
+
+// what an entry holds. notes keep their multi-line body and ssh keys their private key (in
+// the OpenSSH format) where passwords keep the password, so they are encrypted the same way
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum EntryKind {
+    Password,
+    Note,
+    SshKey,
+}
+
+impl EntryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryKind::Password => "password",
+            EntryKind::Note => "note",
+            EntryKind::SshKey => "ssh-key",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<EntryKind> {
+        match s {
+            "password" => Some(EntryKind::Password),
+            "note" => Some(EntryKind::Note),
+            "ssh-key" => Some(EntryKind::SshKey),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Entry {
     pub id: i32,
+    pub kind: EntryKind,
     pub name: String,
     pub login: String,
-    pub password: Vec<u8>, // encrypted
-    pub comment: String
+    pub password: Vec<u8>, // encrypted, the body for notes
+    pub comment: String,
+    pub otp: Option<Vec<u8>>, // encrypted otpauth:// uri
+    // unix times, None if unknown (entries from before they were tracked) or never used
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    pub password_changed_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub folder: String, // slash-separated path like work/aws/prod, empty at the top level
+    pub tags: Vec<String>, // sorted
+    pub urls: Vec<String> // normalized, see urls::normalize
 }
 
 impl Entry {
@@ -28,41 +83,252 @@ impl Entry {
         // might be a problem with assuming the next id?
         Entry {
             id: store::get_next_id(),
+            kind: EntryKind::Password,
             name,
             login,
-            password: crypto::encrypt_aes256(password.as_bytes(), master_key),
-            comment
+            password: crypto::encrypt(password.as_bytes(), master_key),
+            comment,
+            otp: None,
+            created_at: Some(unix_time()),
+            updated_at: Some(unix_time()),
+            password_changed_at: Some(unix_time()),
+            last_used_at: None,
+            folder: String::new(),
+            tags: Vec::new(),
+            urls: Vec::new()
+        }
+    }
+
+    // create a note with a plaintext body. need master key
+    pub fn new_note(name: String, body: String, master_key: &[u8]) -> Entry {
+        Entry {
+            kind: EntryKind::Note,
+            ..Entry::new(name, String::new(), body, String::new(), master_key)
         }
     }
 
     // get decrypted password. need master key
-    pub fn get_password(&self, master_key: &[u8]) -> String {
-        let decrypted_password_bytes = crypto::decrypt_aes256(&self.password, master_key);
-        String::from_utf8(decrypted_password_bytes).unwrap()
+    pub fn get_password(&self, master_key: &[u8]) -> Result<String, Error> {
+        let decrypted_password_bytes = crypto::decrypt(&self.password, master_key)?;
+        String::from_utf8(decrypted_password_bytes).map_err(|_| Error::DecryptionFailed)
+    }
+
+    // get decrypted OTP secret, if the entry has one. need master key
+    pub fn get_otp(&self, master_key: &[u8]) -> Result<Option<Otp>, Error> {
+        match &self.otp {
+            Some(encrypted) => {
+                let uri = String::from_utf8(crypto::decrypt(encrypted, master_key)?)
+                    .map_err(|_| Error::DecryptionFailed)?;
+                Ok(Some(Otp::from_uri(&uri)?))
+            },
+            None => Ok(None)
+        }
+    }
+
+    pub fn set_otp(&mut self, otp: Option<&Otp>, master_key: &[u8]) {
+        self.otp = otp.map(|otp| crypto::encrypt(otp.to_uri().as_bytes(), master_key));
     }
 
     // for csv exporting (serialization)
-    pub fn decrypted(&self, master_key: &[u8]) -> DecryptedEntry {
-        DecryptedEntry {
+    pub fn decrypted(&self, master_key: &[u8]) -> Result<DecryptedEntry, Error> {
+        Ok(DecryptedEntry {
             id: self.id,
             name: self.name.clone(),
             login: self.login.clone(),
-            password: self.get_password(master_key),
-            comment: self.comment.clone()
+            password: self.get_password(master_key)?,
+            comment: self.comment.clone(),
+            otp: self.get_otp(master_key)?.map(|otp| otp.to_uri()).unwrap_or_default(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            password_changed_at: self.password_changed_at,
+            last_used_at: self.last_used_at,
+            fields: encode_fields(&store::get_fields(self.id)?, master_key)?,
+            folder: self.folder.clone(),
+            tags: self.tags.join(","),
+            urls: self.urls.join(" "),
+            kind: self.kind.as_str().to_string(),
+            ssh_confirm: match self.kind {
+                EntryKind::SshKey => store::get_ssh_key(self.id)?.map(|key| key.confirm.to_string()).unwrap_or_default(),
+                _ => String::new(),
+            }
+        })
+    }
+
+    // replace the password with a new plaintext one. need master key
+    pub fn set_password(&mut self, password: String, master_key: &[u8]) {
+        self.password = crypto::encrypt(password.as_bytes(), master_key);
+        self.password_changed_at = Some(unix_time());
+    }
+
+    // non-secret fields, for editing as toml
+    pub fn editable(&self) -> EditableEntry {
+        EditableEntry {
+            name: self.name.clone(),
+            login: self.login.clone(),
+            comment: self.comment.clone(),
+            folder: self.folder.clone(),
+            tags: self.tags.clone(),
+            urls: self.urls.clone()
         }
     }
 
+    pub fn apply(&mut self, fields: EditableEntry) -> Result<(), Error> {
+        if fields.name.trim().is_empty() {
+            return Err(Error::InvalidEntry(String::from("name cannot be empty")));
+        }
+
+        if fields.name.contains('@') {
+            return Err(Error::InvalidEntry(String::from("name cannot contain '@'")));
+        }
+
+        let mut tags = fields.tags.iter().map(|tag| parse_tag(tag)).collect::<Result<Vec<String>, Error>>()?;
+        tags.sort();
+        tags.dedup();
+
+        let mut urls: Vec<String> = Vec::new();
+        for url in &fields.urls {
+            let url = urls::normalize(url)?;
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+
+        self.name = fields.name;
+        self.login = fields.login;
+        self.comment = fields.comment;
+        self.folder = normalize_folder(&fields.folder);
+        self.tags = tags;
+        self.urls = urls;
+
+        Ok(())
+    }
+
     pub fn as_table_row(&self) -> Vec<CellStruct> {
         vec![
             self.id.cell(),
+            self.kind.as_str().cell(),
             (&self.name).cell(),
             (&self.login).cell(),
-            "********".cell(),
-            (&self.comment).cell()
+            // the body of a note or a private key is never shown, not even masked
+            if self.kind == EntryKind::Password { "********" } else { "" }.cell(),
+            (&self.comment).cell(),
+            (&self.folder).cell(),
+            self.tags.join(", ").cell(),
+            self.urls.join("\n").cell()
         ]
     }
 }
 
+// a custom field of an entry (security question answers, PINs, account numbers...).
+// secret values are encrypted like passwords, plain ones are stored as text
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub name: String,
+    pub value: Vec<u8>, // encrypted if secret, utf-8 otherwise
+    pub secret: bool,
+}
+
+impl Field {
+    pub fn new(name: String, value: String, secret: bool, master_key: &[u8]) -> Result<Field, Error> {
+        if name.trim().is_empty() {
+            return Err(Error::InvalidEntry(String::from("field name cannot be empty")));
+        }
+
+        let value = if secret { crypto::encrypt(value.as_bytes(), master_key) } else { value.into_bytes() };
+
+        Ok(Field {
+            name,
+            value,
+            secret,
+        })
+    }
+
+    // need master key for secret fields
+    pub fn get_value(&self, master_key: &[u8]) -> Result<String, Error> {
+        let value = if self.secret { crypto::decrypt(&self.value, master_key)? } else { self.value.clone() };
+        String::from_utf8(value).map_err(|_| Error::DecryptionFailed)
+    }
+}
+
+// for csv exporting, fields go in a single column as a json array
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DecryptedField {
+    pub name: String,
+    pub value: String,
+    pub secret: bool,
+}
+
+pub fn encode_fields(fields: &[Field], master_key: &[u8]) -> Result<String, Error> {
+    if fields.is_empty() {
+        return Ok(String::new());
+    }
+
+    let decrypted = fields
+        .iter()
+        .map(|field| Ok(DecryptedField {
+            name: field.name.clone(),
+            value: field.get_value(master_key)?,
+            secret: field.secret,
+        }))
+        .collect::<Result<Vec<DecryptedField>, Error>>()?;
+
+    Ok(serde_json::to_string(&decrypted).unwrap())
+}
+
+pub fn decode_fields(encoded: &str, master_key: &[u8]) -> Result<Vec<Field>, Error> {
+    if encoded.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let decrypted: Vec<DecryptedField> = serde_json::from_str(encoded)
+        .map_err(|e| Error::InvalidEntry(format!("invalid fields ({})", e)))?;
+
+    decrypted
+        .into_iter()
+        .map(|field| Field::new(field.name, field.value, field.secret, master_key))
+        .collect()
+}
+
+// a file attached to an entry, without its content (see attachments::read)
+pub struct Attachment {
+    pub id: i32,
+    pub entry_id: i32,
+    pub name: String,
+    pub size: u64, // bytes, unencrypted
+    pub created_at: Option<i64>,
+}
+
+// public half and settings of an ssh key entry, the private key is the entry's password
+#[derive(Clone, Debug)]
+pub struct SshKey {
+    pub entry_id: i32,
+    pub name: String, // of the entry
+    pub public_key: String, // OpenSSH format, "ssh-ed25519 AAAA... comment"
+    pub confirm: bool, // ask before every use by `vodka ssh-agent`
+}
+
+// a password an entry has had. the current one has no replaced_at
+pub struct PasswordVersion {
+    pub version: usize,
+    pub password: Vec<u8>, // encrypted
+    pub set_at: Option<i64>,
+    pub replaced_at: Option<i64>,
+}
+
+// every version of the entry's password, oldest first, ending with the current one
+pub fn get_password_versions(entry: &Entry) -> Result<Vec<PasswordVersion>, Error> {
+    let mut versions = store::get_password_history(entry.id)?;
+
+    versions.push(PasswordVersion {
+        version: versions.len() + 1,
+        password: entry.password.clone(),
+        set_at: entry.password_changed_at,
+        replaced_at: None,
+    });
+
+    Ok(versions)
+}
+
 // for csv exporting (serialization)
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DecryptedEntry {
@@ -70,7 +336,72 @@ pub struct DecryptedEntry {
     pub name: String,
     pub login: String,
     pub password: String,
-    pub comment: String
+    pub comment: String,
+    pub otp: String, // otpauth:// uri, empty if none
+    pub created_at: Option<i64>, // unix times, empty if unknown
+    pub updated_at: Option<i64>,
+    pub password_changed_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub fields: String, // json array of custom fields, empty if none
+    pub folder: String,
+    pub tags: String, // comma-separated
+    pub urls: String, // space-separated
+    pub kind: String, // password, note or ssh-key
+    pub ssh_confirm: String // true or false for ssh keys, empty otherwise
+}
+
+// fields of an entry that can be edited in $EDITOR (never the password)
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EditableEntry {
+    pub name: String,
+    pub login: String,
+    pub comment: String,
+    #[serde(default)]
+    pub folder: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub urls: Vec<String>
+}
+
+// "/work//aws/" is work/aws
+pub fn normalize_folder(folder: &str) -> String {
+    folder
+        .split('/')
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+// tags are exported comma-separated, so they can't contain commas
+pub fn parse_tag(tag: &str) -> Result<String, Error> {
+    let tag = tag.trim();
+
+    if tag.is_empty() {
+        return Err(Error::InvalidEntry(String::from("tag cannot be empty")));
+    }
+    if tag.contains(',') {
+        return Err(Error::InvalidEntry(String::from("tag cannot contain ','")));
+    }
+
+    Ok(tag.to_string())
+}
+
+// entries with every one of the tags, in the folder or one of its subfolders
+pub fn filter_entries(entries: Vec<Entry>, tags: &[String], folder: Option<&str>) -> Vec<Entry> {
+    let folder = folder.map(normalize_folder).unwrap_or_default();
+
+    entries
+        .into_iter()
+        .filter(|entry| tags.iter().all(|tag| entry.tags.contains(tag)))
+        .filter(|entry| {
+            folder.is_empty()
+                || entry.folder == folder
+                || entry.folder.starts_with(&format!("{}/", folder))
+        })
+        .collect()
 }
 
 #[derive(Debug)]
@@ -81,6 +412,20 @@ pub enum Error {
     MasterKeyFileNotFound,
     CellarFileNotFound,
     ConfigKeyNotFound(String),
+    DecryptionFailed,
+    InvalidKdf,
+    HashFailed,
+    InvalidEntry(String),
+    AgentFailed(String),
+    InvalidOtp(String),
+    InvalidPasswordSpec(String),
+    InvalidBreachDb(String),
+    InvalidUrl(String),
+    InvalidPattern(String),
+    InvalidQuery(String),
+    CellarTooNew(u32, u32),
+    AttachmentTooLarge(u64, u64),
+    SshKey(String),
     CsvError(csv::Error),
     RusqliteError(rusqlite::Error),
     IOError(std::io::Error),
@@ -95,6 +440,20 @@ impl fmt::Display for Error {
             Error::MasterKeyFileNotFound => write!(f, "{} file not found", get_vodka_path(".master_key").display()),
             Error::CellarFileNotFound => write!(f, "{} file not found", get_cellar_path().display()),
             Error::ConfigKeyNotFound(s) => write!(f, "{} file not found", s),
+            Error::DecryptionFailed => write!(f, "failed to decrypt (wrong key or corrupted data)"),
+            Error::InvalidKdf => write!(f, "invalid key derivation parameters in cellar"),
+            Error::HashFailed => write!(f, "failed to hash the master key"),
+            Error::InvalidEntry(s) => write!(f, "invalid entry: {}", s),
+            Error::AgentFailed(s) => write!(f, "agent error: {}", s),
+            Error::InvalidOtp(s) => write!(f, "invalid OTP secret: {}", s),
+            Error::InvalidPasswordSpec(s) => write!(f, "invalid password options: {}", s),
+            Error::InvalidBreachDb(s) => write!(f, "invalid breach file: {}", s),
+            Error::InvalidUrl(s) => write!(f, "invalid URL: {}", s),
+            Error::InvalidPattern(s) => write!(f, "invalid regex: {}", s),
+            Error::InvalidQuery(s) => write!(f, "invalid search query: {}", s),
+            Error::AttachmentTooLarge(size, limit) => write!(f, "file is {} bytes, over the attachment-max-size of {} bytes", size, limit),
+            Error::SshKey(s) => write!(f, "SSH key error: {}", s),
+            Error::CellarTooNew(version, latest) => write!(f, "cellar was written by a newer version of vodka (schema version {}, this version supports up to {}), please upgrade", version, latest),
             Error::CsvError(err) => write!(f, "CSV error: {}", err),
             Error::RusqliteError(err) => write!(f, "SQLite error: {}", err),
             Error::IOError(err) => write!(f, "IO error: {}", err),
@@ -120,6 +479,13 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<ssh_key::Error> for Error {
+    fn from(err: ssh_key::Error) -> Self {
+        Error::SshKey(err.to_string())
+    }
+}
+
+#[allow(clippy::large_enum_variant)] // short-lived, not worth boxing
 pub enum SearchResult {
     OneResult(Entry),
     NoResults,
@@ -132,7 +498,19 @@ pub fn get_cellar_path() -> PathBuf {
 
 pub fn get_db() -> Connection {
     let cellar_path = get_cellar_path();
-    Connection::open(cellar_path).unwrap()
+    let mut connection = Connection::open(&cellar_path).unwrap();
+
+    if let Err(e) = migrations::migrate(&mut connection, &cellar_path) {
+        eprintln!("Error while opening cellar: {}", e);
+        std::process::exit(1);
+    }
+
+    connection
+}
+
+// seconds since the unix epoch, as timestamps are stored in the cellar
+pub fn unix_time() -> i64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64
 }
 
 // get absolute path of a file in .vodka folder
@@ -173,19 +551,148 @@ pub fn parse_fullname(fullname: String) -> (String, String) {
     }
 }
 
-pub fn add_entry(entry: Entry) -> Result<(), Error> {
-    store::add_entry(entry.name, entry.login, &entry.password, entry.comment)?;
-    
+// returns the id of the new entry
+pub fn add_entry(entry: Entry) -> Result<i32, Error> {
+    store::add_entry(&entry, &[])
+}
+
+// wraps the data key with a key derived from the new master key (with a fresh salt)
+// and replaces .master_key. entries stay encrypted with the data key, so none are touched
+pub fn change_master(data_key: &[u8], new_master_key: String) -> Result<(), Error> {
+    let new_kdf = crypto::new_kdf();
+    let new_wrapping_key = crypto::derive_key(new_master_key.as_bytes(), &new_kdf)?;
+
+    // the new .master_key is ready before the data key is rewrapped and only replaces the old
+    // one after, so a failure on the way leaves the old master key working
+    let new_master_hash = store::write_temp_file(".master_key", &setup::hash_master(&new_master_key)?)?;
+
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    let old_kdf = store::get_vault_value(&transaction, "kdf");
+    let old_data_key = store::get_vault_value(&transaction, "data-key");
+    store::set_vault_value(&transaction, "kdf", new_kdf.as_bytes())?;
+    store::set_vault_value(&transaction, "data-key", &crypto::encrypt(data_key, &new_wrapping_key))?;
+
+    if let Err(e) = transaction.commit() {
+        std::fs::remove_file(&new_master_hash)?;
+        return Err(Error::from(e));
+    }
+
+    // atomic, .master_key is never missing or half written. if it can't be replaced the old
+    // master key still checks, so the data key goes back to being wrapped by it
+    if let Err(e) = std::fs::rename(&new_master_hash, get_vodka_path(".master_key")) {
+        let transaction = connection.transaction()?;
+        if let (Some(old_kdf), Some(old_data_key)) = (old_kdf, old_data_key) {
+            store::set_vault_value(&transaction, "kdf", &old_kdf)?;
+            store::set_vault_value(&transaction, "data-key", &old_data_key)?;
+        }
+        transaction.commit()?;
+        let _ = std::fs::remove_file(&new_master_hash);
+
+        return Err(Error::from(e));
+    }
+
     Ok(())
 }
 
+// unwraps the data key that encrypts every entry, using a verified master key.
+// cellars written by older versions of vodka are upgraded on the way, in a single
+// transaction, so they are either fully upgraded or untouched
+pub fn open_cellar(master_key: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    // key the entries are currently encrypted with. starts as the key of cellars
+    // from before the vault key was salted and changes as the cellar is upgraded
+    let mut entries_key = crypto::hash_sha256(master_key);
+
+    // cellars without a cipher marker still hold unauthenticated block-by-block AES blobs
+    if store::get_vault_value(&transaction, "cipher").is_none() {
+        store::update_passwords(&transaction, |password| {
+            Ok(crypto::encrypt(&crypto::decrypt_legacy_aes256(password, &entries_key), &entries_key))
+        })?;
+        store::set_vault_value(&transaction, "cipher", b"aes-256-gcm")?;
+    }
+
+    let wrapping_key = match store::get_vault_value(&transaction, "kdf") {
+        Some(kdf) => {
+            let kdf = String::from_utf8(kdf).map_err(|_| Error::InvalidKdf)?;
+            entries_key = crypto::derive_key(master_key, &kdf)?;
+            entries_key.clone()
+        },
+        None => {
+            let kdf = crypto::new_kdf();
+            store::set_vault_value(&transaction, "kdf", kdf.as_bytes())?;
+            crypto::derive_key(master_key, &kdf)?
+        }
+    };
+
+    let data_key = match store::get_vault_value(&transaction, "data-key") {
+        Some(wrapped) => crypto::decrypt(&wrapped, &wrapping_key)?,
+        None => {
+            let data_key = crypto::generate_key();
+
+            store::update_passwords(&transaction, |password| {
+                Ok(crypto::encrypt(&crypto::decrypt(password, &entries_key)?, &data_key))
+            })?;
+            store::set_vault_value(&transaction, "data-key", &crypto::encrypt(&data_key, &wrapping_key))?;
+
+            data_key
+        }
+    };
+
+    transaction.commit()?;
+
+    Ok(data_key)
+}
+
+// orders of `vodka list --sort`
+#[derive(Clone, Copy)]
+pub enum SortKey {
+    Id,
+    Name,
+    Created,
+    Updated,
+    PasswordChanged,
+    LastUsed,
+}
+
+impl SortKey {
+    pub fn parse(s: &str) -> Option<SortKey> {
+        match s {
+            "id" => Some(SortKey::Id),
+            "name" => Some(SortKey::Name),
+            "created" => Some(SortKey::Created),
+            "updated" => Some(SortKey::Updated),
+            "changed" => Some(SortKey::PasswordChanged),
+            "used" => Some(SortKey::LastUsed),
+            _ => None,
+        }
+    }
+}
+
+// timestamps sort newest first, unknown ones last
+pub fn sort_entries(entries: &mut [Entry], key: SortKey) {
+    let newest_first = |a: Option<i64>, b: Option<i64>| b.cmp(&a);
+
+    match key {
+        SortKey::Id => entries.sort_by_key(|entry| entry.id),
+        SortKey::Name => entries.sort_by(|a, b| (&a.name, &a.login).cmp(&(&b.name, &b.login))),
+        SortKey::Created => entries.sort_by(|a, b| newest_first(a.created_at, b.created_at)),
+        SortKey::Updated => entries.sort_by(|a, b| newest_first(a.updated_at, b.updated_at)),
+        SortKey::PasswordChanged => entries.sort_by(|a, b| newest_first(a.password_changed_at, b.password_changed_at)),
+        SortKey::LastUsed => entries.sort_by(|a, b| newest_first(a.last_used_at, b.last_used_at)),
+    }
+}
+
 // will always return a single entry (for now?)
 pub fn get_entry(name: String, login: String, strict: bool) -> SearchResult {
     let result_entries: Vec<Entry> = store::search_entries(name, login.clone());
     
     if result_entries.len() == 1 {
         return SearchResult::OneResult(result_entries[0].clone());
-    } else if result_entries.len() == 0 {
+    } else if result_entries.is_empty() {
         return SearchResult::NoResults;
     }
 
@@ -199,8 +706,14 @@ pub fn get_entry(name: String, login: String, strict: bool) -> SearchResult {
     SearchResult::ManyResults(result_entries)
 }
 
-// Ask the user for the master key. Once verified, returns the SHA-256 of the password
+// Ask the user for the master key. Once verified, returns the data key that encrypts the entries
+// Asks a running agent first (see `vodka agent`)
 pub fn unlock() -> Vec<u8> {
+    #[cfg(unix)]
+    if let Some(key) = agent::get_key() {
+        return key;
+    }
+
     unlock_with_prompt("Enter master key: ")
 }
 
@@ -210,7 +723,7 @@ pub fn unlock_if_required(command: &str) -> Option<Vec<u8>> {
         &format!("requires-key.{}", command), 
         true
     ) {
-        Some(unlock_with_prompt("Enter master key: "))
+        Some(unlock())
     } else {
         None
     }
@@ -221,7 +734,13 @@ pub fn unlock_with_prompt(prompt: &str) -> Vec<u8> {
     
     if let Some(verified) = crypto::verify_password(master_key_plaintext.as_bytes()) {
         if verified {
-            return crypto::hash_sha256(master_key_plaintext.as_bytes());
+            match open_cellar(master_key_plaintext.as_bytes()) {
+                Ok(key) => return key,
+                Err(e) => {
+                    eprintln!("Error while opening cellar: {}", e);
+                    std::process::exit(1);
+                }
+            }
         }
     }
     
@@ -238,6 +757,64 @@ pub fn vodka_is_setup() -> bool {
     true
 }
 
+// estimates the strength of a password the user typed and applies the thresholds of [strength]
+// in config.toml (master keys have their own). below refuse-below the password is refused,
+// below warn-below the user has to confirm it. returns whether the password can be used
+pub fn check_password_strength(password: &str, user_inputs: &[&str], is_master_key: bool) -> bool {
+    if is_master_key && password.is_empty() {
+        eprintln!("Error: The master key cannot be empty");
+        return false;
+    }
+
+    let prefix = if is_master_key { "strength.master-key-" } else { "strength." };
+    let warn_below = config::get_or(&format!("{}warn-below", prefix), 3);
+    let refuse_below = config::get_or(&format!("{}refuse-below", prefix), if is_master_key { 2 } else { 0 });
+
+    let estimate = strength::estimate(password, user_inputs);
+    if i32::from(estimate.score) >= warn_below.max(refuse_below) {
+        return true;
+    }
+
+    eprintln!(
+        "Strength: {} ({}/4), estimated time to crack offline: {}",
+        estimate.describe_score(),
+        estimate.score,
+        estimate.describe_crack_time()
+    );
+    if let Some(warning) = &estimate.warning {
+        eprintln!("Warning: {}", warning);
+    }
+    for suggestion in &estimate.suggestions {
+        eprintln!("  - {}", suggestion);
+    }
+
+    if i32::from(estimate.score) < refuse_below {
+        eprintln!("Error: This {} is too weak", if is_master_key { "master key" } else { "password" });
+        return false;
+    }
+
+    ask_for_confirmation(format!("\nThis {} is weak.", if is_master_key { "master key" } else { "password" }))
+}
+
+// looks the password up in the breach file set as breach-db in config.toml, if any.
+// returns whether the password can be used (not breached, or confirmed by the user)
+pub fn check_password_breaches(password: &str) -> bool {
+    let path: String = config::get_or("breach-db", String::new());
+    if path.is_empty() {
+        return true;
+    }
+
+    let count = breach::BreachDb::open(&get_absolute_path(&path)).and_then(|mut db| db.count(password));
+    match count {
+        Ok(Some(count)) => ask_for_confirmation(format!("Warning: This password has appeared {} times in data breaches.", count)),
+        Ok(None) => true,
+        Err(e) => {
+            eprintln!("Warning: could not check {} for breaches: {}", path, e);
+            true
+        }
+    }
+}
+
 pub fn ask_for_confirmation(message: String) -> bool {
     eprintln!("{}\n", message);
     eprint!("Proceed? [y/N]: ");
@@ -245,14 +822,159 @@ pub fn ask_for_confirmation(message: String) -> bool {
     let mut selection = String::new();
     std::io::stdin().read_line(&mut selection).expect("Failed to read line");
     
-    if selection.trim().to_lowercase().chars().next().unwrap() == 'y' {
+    if selection.trim().to_lowercase().starts_with('y') {
         return true;
     }
 
     false
 }
 
+// opens content in $EDITOR (or $VISUAL, or vi) and returns the saved file
+pub fn edit_in_editor(content: &str, extension: &str) -> Result<String, Error> {
+    let editor = std::env::var("EDITOR")
+        .or_else(|_| std::env::var("VISUAL"))
+        .unwrap_or(String::from("vi"));
+    let mut editor_args = editor.split_whitespace();
+    let editor_program = editor_args.next().unwrap_or("vi");
+
+    let file_path = std::env::temp_dir().join(format!(
+        "vodka-{}-{}.{}",
+        std::process::id(),
+        crypto::get_random_suffix(),
+        extension
+    ));
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&file_path)?.write_all(content.as_bytes())?;
+
+    let status = std::process::Command::new(editor_program)
+        .args(editor_args)
+        .arg(&file_path)
+        .status();
+    let edited = std::fs::read_to_string(&file_path);
+    std::fs::remove_file(&file_path)?;
+
+    if !status?.success() {
+        return Err(Error::InvalidEntry(format!("{} exited with an error", editor)));
+    }
+
+    Ok(edited?)
+}
+
+// edit the non-secret fields of an entry in $EDITOR, re-opening the editor until they are valid
+pub fn edit_entry_in_editor(entry: &mut Entry) -> Result<(), Error> {
+    let mut content = toml::to_string(&entry.editable()).unwrap();
+
+    loop {
+        content = edit_in_editor(&content, "toml")?;
+
+        let result = toml::from_str::<EditableEntry>(&content)
+            .map_err(|e| Error::InvalidEntry(e.message().to_string()))
+            .and_then(|fields| entry.apply(fields));
+
+        match result {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                if !ask_for_confirmation(format!("Error: {}\nThe editor will be opened again.", e)) {
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+// copies content, marked as excluded from clipboard managers' histories where supported.
+// unless clipboard-timeout is 0, a detached `vodka clear-clipboard` is spawned to clear it afterwards
 pub fn copy_to_clipboard(content: String) {
     let mut clipboard = Clipboard::new().unwrap();
-    clipboard.set_text(content).unwrap();
+    let previous = clipboard.get_text().ok();
+
+    set_clipboard_secret(&mut clipboard, &content).unwrap();
+
+    let timeout = config::get_or("clipboard-timeout", 30);
+    if timeout > 0 {
+        if let Err(e) = spawn_clipboard_clearer(timeout, &content, previous) {
+            eprintln!("Warning: failed to schedule clearing the clipboard ({})", e);
+        }
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+fn set_clipboard_secret(clipboard: &mut Clipboard, content: &str) -> Result<(), arboard::Error> {
+    use arboard::SetExtLinux;
+    clipboard.set().exclude_from_history().text(content)
+}
+
+#[cfg(windows)]
+fn set_clipboard_secret(clipboard: &mut Clipboard, content: &str) -> Result<(), arboard::Error> {
+    use arboard::SetExtWindows;
+    clipboard.set().exclude_from_history().exclude_from_cloud().text(content)
+}
+
+#[cfg(target_os = "macos")]
+fn set_clipboard_secret(clipboard: &mut Clipboard, content: &str) -> Result<(), arboard::Error> {
+    use arboard::SetExtApple;
+    clipboard.set().exclude_from_history().text(content)
+}
+
+#[cfg(any(target_os = "android", target_os = "emscripten"))]
+fn set_clipboard_secret(clipboard: &mut Clipboard, content: &str) -> Result<(), arboard::Error> {
+    clipboard.set_text(content)
+}
+
+// the secret itself is never handed to the helper, only its hash (and what to restore).
+// stdin: "<sha256 of secret>\n<previous clipboard content>"
+fn spawn_clipboard_clearer(timeout: i32, secret: &str, previous: Option<String>) -> Result<(), Error> {
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .arg("clear-clipboard")
+        .arg(timeout.to_string())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+
+    // detach from the terminal's process group so closing it doesn't kill the helper
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    let mut child = command.spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    let secret_hash = crypto::hash_sha256(secret.as_bytes());
+
+    writeln!(stdin, "{}", crypto::to_hex(&secret_hash))?;
+    stdin.write_all(previous.unwrap_or_default().as_bytes())?;
+
+    Ok(())
+}
+
+// run by the detached helper. after the timeout, clears the clipboard if it still holds
+// the secret and restores what was there before
+pub fn clear_clipboard_after(timeout: u64) -> Result<(), Error> {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let (secret_hash, previous) = input.split_once('\n').unwrap_or((&input, ""));
+
+    std::thread::sleep(std::time::Duration::from_secs(timeout));
+
+    let mut clipboard = match Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(_) => return Ok(()),
+    };
+
+    if let Ok(current) = clipboard.get_text() {
+        if crypto::to_hex(&crypto::hash_sha256(current.as_bytes())) != secret_hash {
+            return Ok(()); // something else was copied since
+        }
+
+        let _ = if previous.is_empty() {
+            clipboard.clear()
+        } else {
+            clipboard.set_text(previous)
+        };
+    }
+
+    Ok(())
 }
\ No newline at end of file
@@ -1,6 +1,13 @@
-use clap::{Arg, ArgAction, Command};
-use vodka::{config, crypto, display, setup, store, transport};
-use vodka::{Entry, SearchResult};
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+use vodka::{attachments, audit, breach, config, crypto, display, otp, picker, search, setup, ssh, store, transport, urls};
+use vodka::audit::AuditOptions;
+use vodka::generator::{Capitalization, Passphrase, PassphraseSpec, PasswordSpec};
+use vodka::otp::Otp;
+use vodka::search::SearchMode;
+use vodka::urls::UrlMatch;
+use vodka::{Entry, EntryKind, Field, PasswordVersion, SearchResult, SortKey, SshKey};
 
 fn cli() -> Command {
     Command::new("vodka")
@@ -8,6 +15,7 @@ fn cli() -> Command {
         .subcommand(
             Command::new("setup")
                 .about("Sets up vodka")
+                .args(passphrase_spec_args())
         )
         .subcommand(
             Command::new("add")
@@ -18,6 +26,30 @@ fn cli() -> Command {
                     .long("random")
                     .short('r')
                     .num_args(0))
+                .arg(Arg::new("FOLDER")
+                    .long("folder")
+                    .help("Folder path, like work/aws/prod")
+                    .num_args(1))
+                .arg(Arg::new("TAG")
+                    .long("tag")
+                    .help("Tag the entry (can be repeated)")
+                    .action(ArgAction::Append))
+                .arg(Arg::new("URL")
+                    .long("url")
+                    .help("Website of the entry (can be repeated), see `vodka find-url`")
+                    .action(ArgAction::Append))
+                .args(password_spec_args().into_iter().map(|arg| arg.requires("RANDOM").conflicts_with("PASSPHRASE")))
+                .args(passphrase_spec_args().into_iter().map(|arg| arg.requires("RANDOM")))
+        )
+        .subcommand(
+            Command::new("generate")
+                .about("Generate a random password or passphrase without storing it")
+                .arg(Arg::new("COPY")
+                    .long("copy")
+                    .short('c')
+                    .num_args(0))
+                .args(password_spec_args().into_iter().map(|arg| arg.conflicts_with("PASSPHRASE")))
+                .args(passphrase_spec_args())
         )
         .subcommand(
             Command::new("copy")
@@ -30,22 +62,440 @@ fn cli() -> Command {
                     .short('i')
                     .required_unless_present("FULLNAME")
                     .num_args(1))
+                .arg(Arg::new("VERSION")
+                    .long("version")
+                    .short('v')
+                    .help("Copy an earlier password instead, see `vodka history`")
+                    .value_parser(clap::value_parser!(usize)))
+                .arg(Arg::new("FIELD")
+                    .long("field")
+                    .short('f')
+                    .help("Copy a custom field instead, see `vodka field`")
+                    .num_args(1)
+                    .conflicts_with("VERSION"))
         )
         .subcommand(
-            Command::new("search")
-                .about("Search for an entry with fullname")
+            Command::new("history")
+                .about("List the previous passwords of an entry")
+                .arg(Arg::new("FULLNAME")
+                    .required(false)
+                    .conflicts_with("ID"))
+                .arg(Arg::new("ID")
+                    .long("id")
+                    .short('i')
+                    .required_unless_present("FULLNAME")
+                    .num_args(1))
+        )
+        .subcommand(
+            Command::new("rollback")
+                .about("Restore an earlier password of an entry (the previous one by default)")
+                .arg(Arg::new("FULLNAME")
+                    .required(false)
+                    .conflicts_with("ID"))
+                .arg(Arg::new("ID")
+                    .long("id")
+                    .short('i')
+                    .required_unless_present("FULLNAME")
+                    .num_args(1))
+                .arg(Arg::new("VERSION")
+                    .long("version")
+                    .short('v')
+                    .value_parser(clap::value_parser!(usize)))
+        )
+        .subcommand(
+            Command::new("edit")
+                .about("Edit an existing entry")
+                .arg(Arg::new("FULLNAME")
+                    .required(false)
+                    .conflicts_with("ID"))
+                .arg(Arg::new("ID")
+                    .long("id")
+                    .short('i')
+                    .required_unless_present("FULLNAME")
+                    .num_args(1))
+                .arg(Arg::new("NAME")
+                    .long("name")
+                    .num_args(1))
+                .arg(Arg::new("LOGIN")
+                    .long("login")
+                    .num_args(1))
+                .arg(Arg::new("COMMENT")
+                    .long("comment")
+                    .num_args(1))
+                .arg(Arg::new("FOLDER")
+                    .long("folder")
+                    .help("Folder path, like work/aws/prod (empty for the top level)")
+                    .num_args(1))
+                .arg(Arg::new("PASSWORD")
+                    .long("password")
+                    .short('p')
+                    .num_args(0)
+                    .conflicts_with("RANDOM"))
+                .arg(Arg::new("RANDOM")
+                    .long("random")
+                    .short('r')
+                    .num_args(0))
+                .arg(Arg::new("EDITOR")
+                    .long("editor")
+                    .short('e')
+                    .num_args(0)
+                    .conflicts_with_all(["NAME", "LOGIN", "COMMENT", "FOLDER", "PASSWORD", "RANDOM"]))
+        )
+        .subcommand(
+            Command::new("otp")
+                .about("Print the current one-time password (TOTP/HOTP) of an entry")
                 .arg(Arg::new("FULLNAME")
+                    .required(false)
+                    .conflicts_with("ID"))
+                .arg(Arg::new("ID")
+                    .long("id")
+                    .short('i')
+                    .required_unless_present("FULLNAME")
+                    .num_args(1))
+                .arg(Arg::new("COPY")
+                    .long("copy")
+                    .short('c')
+                    .num_args(0))
+                .arg(Arg::new("SET")
+                    .long("set")
+                    .short('s')
+                    .help("Prompt for a base32 secret or an otpauth:// uri")
+                    .num_args(0)
+                    .conflicts_with_all(["COPY", "REMOVE"]))
+                .arg(Arg::new("REMOVE")
+                    .long("remove")
+                    .num_args(0)
+                    .conflicts_with("COPY"))
+                .arg(Arg::new("ALGORITHM")
+                    .long("algorithm")
+                    .requires("SET")
+                    .value_parser(["sha1", "sha256", "sha512"])
+                    .default_value("sha1"))
+                .arg(Arg::new("DIGITS")
+                    .long("digits")
+                    .requires("SET")
+                    .value_parser(clap::value_parser!(u32).range(6..=8))
+                    .default_value("6"))
+                .arg(Arg::new("PERIOD")
+                    .long("period")
+                    .requires("SET")
+                    .value_parser(clap::value_parser!(u64).range(1..))
+                    .default_value("30"))
+                .arg(Arg::new("COUNTER")
+                    .long("counter")
+                    .help("Use HOTP starting at this counter instead of TOTP")
+                    .requires("SET")
+                    .value_parser(clap::value_parser!(u64)))
+        )
+        .subcommand(
+            Command::new("field")
+                .about("Manage the custom fields of an entry")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("set")
+                        .about("Add or replace a field. The value is prompted for unless --value is given")
+                        .args(entry_item_args("FIELD"))
+                        .arg(Arg::new("SECRET")
+                            .long("secret")
+                            .short('s')
+                            .help("Encrypt the value like a password")
+                            .num_args(0))
+                        .arg(Arg::new("VALUE")
+                            .long("value")
+                            .help("Value of a plain field")
+                            .num_args(1)
+                            .conflicts_with("SECRET"))
+                )
+                .subcommand(
+                    Command::new("get")
+                        .about("Print the value of a field")
+                        .args(entry_item_args("FIELD"))
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove a field")
+                        .args(entry_item_args("FIELD"))
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the fields of an entry, secret values are hidden")
+                        .arg(Arg::new("FULLNAME")
+                            .required(false)
+                            .conflicts_with("ID"))
+                        .arg(Arg::new("ID")
+                            .long("id")
+                            .short('i')
+                            .required_unless_present("FULLNAME")
+                            .num_args(1))
+                )
+        )
+        .subcommand(
+            Command::new("note")
+                .about("Manage secure notes, entries whose whole body is encrypted")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a note. The body is read from stdin, or written in $EDITOR when stdin is a terminal")
+                        .arg(Arg::new("NAME")
+                            .required(true))
+                        .arg(Arg::new("FOLDER")
+                            .long("folder")
+                            .help("Folder path, like work/aws/prod")
+                            .num_args(1))
+                        .arg(Arg::new("TAG")
+                            .long("tag")
+                            .help("Tag the note (can be repeated)")
+                            .action(ArgAction::Append))
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Print the body of a note")
+                        .arg(Arg::new("FULLNAME")
+                            .required(false)
+                            .conflicts_with("ID"))
+                        .arg(Arg::new("ID")
+                            .long("id")
+                            .short('i')
+                            .required_unless_present("FULLNAME")
+                            .num_args(1))
+                )
+                .subcommand(
+                    Command::new("edit")
+                        .about("Edit the body of a note in $EDITOR. The previous body is kept, see `vodka history`")
+                        .arg(Arg::new("FULLNAME")
+                            .required(false)
+                            .conflicts_with("ID"))
+                        .arg(Arg::new("ID")
+                            .long("id")
+                            .short('i')
+                            .required_unless_present("FULLNAME")
+                            .num_args(1))
+                )
+        )
+        .subcommand(
+            Command::new("attach")
+                .about("Manage the encrypted file attachments of an entry")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Attach a file, up to attachment-max-size bytes")
+                        .args(entry_item_args("FILE"))
+                        .arg(Arg::new("NAME")
+                            .long("name")
+                            .short('n')
+                            .help("Name of the attachment, the file name by default")
+                            .num_args(1))
+                )
+                .subcommand(
+                    Command::new("ls")
+                        .about("List the attachments of an entry")
+                        .arg(Arg::new("FULLNAME")
+                            .required(false)
+                            .conflicts_with("ID"))
+                        .arg(Arg::new("ID")
+                            .long("id")
+                            .short('i')
+                            .required_unless_present("FULLNAME")
+                            .num_args(1))
+                )
+                .subcommand(
+                    Command::new("get")
+                        .about("Write an attachment to stdout, or to a file only you can read with --output")
+                        .args(entry_item_args("ATTACHMENT"))
+                        .arg(Arg::new("OUTPUT")
+                            .long("output")
+                            .short('o')
+                            .num_args(1))
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove an attachment")
+                        .args(entry_item_args("ATTACHMENT"))
+                )
+        )
+        .subcommand(
+            Command::new("ssh")
+                .about("Manage SSH keys, which `vodka ssh-agent` serves to ssh")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("generate")
+                        .about("Generate a new SSH key")
+                        .arg(Arg::new("NAME")
+                            .required(true))
+                        .arg(Arg::new("TYPE")
+                            .long("type")
+                            .short('t')
+                            .value_parser(["ed25519", "ecdsa", "rsa"])
+                            .default_value("ed25519")
+                            .num_args(1))
+                        .arg(Arg::new("BITS")
+                            .long("bits")
+                            .short('b')
+                            .help("256, 384 or 521 for ecdsa (256 by default), at least 2048 for rsa (3072 by default)")
+                            .value_parser(clap::value_parser!(usize))
+                            .num_args(1))
+                        .arg(Arg::new("COMMENT")
+                            .long("comment")
+                            .short('C')
+                            .help("Comment of the public key")
+                            .num_args(1))
+                        .arg(Arg::new("CONFIRM")
+                            .long("confirm")
+                            .help("Ask before every use of the key by `vodka ssh-agent`")
+                            .num_args(0))
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Import a private key in the OpenSSH format, asking for its passphrase if it has one")
+                        .arg(Arg::new("FILE")
+                            .required(true))
+                        .arg(Arg::new("NAME")
+                            .required(true))
+                        .arg(Arg::new("CONFIRM")
+                            .long("confirm")
+                            .help("Ask before every use of the key by `vodka ssh-agent`")
+                            .num_args(0))
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List SSH keys with their fingerprints")
+                )
+                .subcommand(
+                    Command::new("public-key")
+                        .about("Print the public key of an SSH key, as in authorized_keys")
+                        .arg(Arg::new("FULLNAME")
+                            .required(false)
+                            .conflicts_with("ID"))
+                        .arg(Arg::new("ID")
+                            .long("id")
+                            .short('i')
+                            .required_unless_present("FULLNAME")
+                            .num_args(1))
+                )
+                .subcommand(
+                    Command::new("confirm")
+                        .about("Turn asking before every use of an SSH key on or off")
+                        .args(entry_item_args("STATE"))
+                )
+        )
+        .subcommand(
+            Command::new("tag")
+                .about("Tag entries, or list tags")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a tag to an entry")
+                        .args(entry_item_args("TAG"))
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove a tag from an entry")
+                        .args(entry_item_args("TAG"))
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List every tag and how many entries have it")
+                )
+        )
+        .subcommand(
+            Command::new("url")
+                .about("Manage the urls of an entry")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a url to an entry")
+                        .args(entry_item_args("URL"))
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove a url from an entry")
+                        .args(entry_item_args("URL"))
+                )
+        )
+        .subcommand(
+            Command::new("find-url")
+                .about("Find the entries for a url")
+                .arg(Arg::new("URL")
+                    .required(true))
+                .arg(Arg::new("MATCH")
+                    .long("match")
+                    .short('m')
+                    .help("domain: same registrable domain, host: same host, prefix: same host and path prefix")
+                    .value_parser(["domain", "host", "prefix"])
+                    .default_value("domain"))
+                .arg(Arg::new("COPY")
+                    .long("copy")
+                    .short('c')
+                    .help("Copy the password of the best match (pick one if several match equally well)")
+                    .num_args(0))
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search entries by name, login, comment, folder, tags, urls and plain fields (fuzzy by default)")
+                .arg(Arg::new("QUERY")
                     .required(true))
+                .arg(Arg::new("EXACT")
+                    .long("exact")
+                    .short('e')
+                    .help("Match the query as a fullname (login@name), exactly")
+                    .num_args(0))
+                .arg(Arg::new("SUBSTRING")
+                    .long("substring")
+                    .help("Case-insensitive substring match")
+                    .num_args(0))
+                .arg(Arg::new("REGEX")
+                    .long("regex")
+                    .help("Case-insensitive regex match")
+                    .num_args(0))
+                .arg(Arg::new("TEXT")
+                    .long("text")
+                    .help("Full-text query over name, login, comment, tags and urls: prefix (git*), phrase (\"work laptop\") and boolean (aws AND NOT prod)")
+                    .num_args(0))
+                .group(ArgGroup::new("MODE")
+                    .args(["EXACT", "SUBSTRING", "REGEX", "TEXT"]))
+                .args(listing_args())
         )
         .subcommand(
             Command::new("delete")
-                .about("Delete an entry with its ID")
+                .about("Delete an entry, by fullname or id")
+                .arg(Arg::new("FULLNAME")
+                    .required(false)
+                    .conflicts_with("ID"))
                 .arg(Arg::new("ID")
+                    .long("id")
+                    .short('i')
+                    .required_unless_present("FULLNAME")
+                    .num_args(1))
+        )
+        .subcommand(
+            Command::new("audit")
+                .about("Report reused, weak and old passwords. Exits with 1 if any were found")
+                .arg(Arg::new("JSON")
+                    .long("json")
+                    .num_args(0))
+                .arg(Arg::new("MIN_SCORE")
+                    .long("min-score")
+                    .help("Passwords with a strength score (0-4) below this are weak")
+                    .value_parser(clap::value_parser!(u8).range(0..=4)))
+                .arg(Arg::new("MAX_AGE")
+                    .long("max-age")
+                    .help("Passwords not changed for this many days are old (0 to skip)")
+                    .value_parser(clap::value_parser!(u64)))
+                .arg(Arg::new("BREACH_DB")
+                    .long("breach-db")
+                    .help("Pwned Passwords file (SHA-1 or NTLM, ordered by hash) to check passwords against")
+                    .num_args(1))
+        )
+        .subcommand(
+            Command::new("breach-index")
+                .about("Build an index for a Pwned Passwords file (FILE.idx), which makes lookups much faster")
+                .arg(Arg::new("FILE")
                     .required(true))
         )
         .subcommand(
             Command::new("list")
                 .about("List all existing entries")
+                .args(listing_args())
         )
         .subcommand(
             Command::new("export")
@@ -62,11 +512,48 @@ fn cli() -> Command {
         .subcommand(
             Command::new("change-master")
                 .about("Change the master key")
+                .args(passphrase_spec_args())
         )
         .subcommand(
             Command::new("erase")
                 .about("Erase all existing passwords")
         )
+        .subcommand(
+            Command::new("agent")
+                .about("Start an agent that keeps vodka unlocked until `vodka lock` or agent-timeout seconds of inactivity")
+                .arg(Arg::new("FOREGROUND")
+                    .long("foreground")
+                    .short('f')
+                    .num_args(0))
+                .arg(Arg::new("STDIN_KEY")
+                    .long("stdin-key")
+                    .hide(true)
+                    .num_args(0))
+        )
+        .subcommand(
+            Command::new("ssh-agent")
+                .about("Start an ssh-agent serving the SSH keys of vodka until `vodka lock`. Use it with `eval $(vodka ssh-agent)`")
+                .arg(Arg::new("FOREGROUND")
+                    .long("foreground")
+                    .short('f')
+                    .num_args(0))
+                .arg(Arg::new("STDIN_KEY")
+                    .long("stdin-key")
+                    .hide(true)
+                    .num_args(0))
+        )
+        .subcommand(
+            Command::new("lock")
+                .about("Stop the agent and the ssh-agent, so the master key is required again")
+        )
+        .subcommand(
+            Command::new("clear-clipboard")
+                .about("Clears a copied password from the clipboard after a timeout (used internally by copy)")
+                .hide(true)
+                .arg(Arg::new("TIMEOUT")
+                    .required(true)
+                    .value_parser(clap::value_parser!(u64)))
+        )
         .subcommand(
             Command::new("config")
                 .about("Modify/view the existing configuration")
@@ -81,114 +568,1222 @@ fn cli() -> Command {
         )
 }
 
+// options of the password generator, defaults are taken from [generator] in config.toml
+fn password_spec_args() -> Vec<Arg> {
+    let mut args = vec![
+        Arg::new("LENGTH")
+            .long("length")
+            .short('l')
+            .value_parser(clap::value_parser!(usize)),
+        Arg::new("SYMBOL_SET")
+            .long("symbols")
+            .help("Special characters to choose from")
+            .num_args(1),
+        Arg::new("EXCLUDE_SIMILAR")
+            .long("exclude-similar")
+            .help("Exclude look-alike characters (l1I0O)")
+            .num_args(0),
+        Arg::new("NO_REPEAT")
+            .long("no-repeat")
+            .help("Use every character at most once")
+            .num_args(0),
+    ];
+
+    for (no_id, no_flag, min_id, min_flag) in [
+        ("NO_LOWERCASE", "no-lowercase", "MIN_LOWERCASE", "min-lowercase"),
+        ("NO_UPPERCASE", "no-uppercase", "MIN_UPPERCASE", "min-uppercase"),
+        ("NO_DIGITS", "no-digits", "MIN_DIGITS", "min-digits"),
+        ("NO_SYMBOLS", "no-symbols", "MIN_SYMBOLS", "min-symbols"),
+    ] {
+        args.push(Arg::new(no_id)
+            .long(no_flag)
+            .num_args(0));
+        args.push(Arg::new(min_id)
+            .long(min_flag)
+            .value_parser(clap::value_parser!(usize))
+            .conflicts_with(no_id));
+    }
+
+    args
+}
+
+fn password_spec_from_matches(matches: &ArgMatches) -> PasswordSpec {
+    let mut spec = PasswordSpec::from_config();
+
+    if let Some(&length) = matches.get_one::<usize>("LENGTH") {
+        spec.length = length;
+    }
+    if let Some(symbol_set) = matches.get_one::<String>("SYMBOL_SET") {
+        spec.symbol_set = symbol_set.to_string();
+    }
+    spec.exclude_similar |= matches.get_flag("EXCLUDE_SIMILAR");
+    spec.no_repeat |= matches.get_flag("NO_REPEAT");
+
+    for (name, class) in [
+        ("LOWERCASE", &mut spec.lowercase),
+        ("UPPERCASE", &mut spec.uppercase),
+        ("DIGITS", &mut spec.digits),
+        ("SYMBOLS", &mut spec.symbols),
+    ] {
+        if matches.get_flag(&format!("NO_{}", name)) {
+            class.enabled = false;
+            class.min = 0;
+        }
+        if let Some(&min) = matches.get_one::<usize>(&format!("MIN_{}", name)) {
+            class.min = min;
+        }
+    }
+
+    spec
+}
+
+// `vodka field|tag|url <action> <item> <fullname>`, the item comes first so --id can replace the fullname
+fn entry_item_args(item: &'static str) -> Vec<Arg> {
+    vec![
+        Arg::new(item)
+            .required(true),
+        Arg::new("FULLNAME")
+            .required(false)
+            .conflicts_with("ID"),
+        Arg::new("ID")
+            .long("id")
+            .short('i')
+            .required_unless_present("FULLNAME")
+            .num_args(1),
+    ]
+}
+
+// how list and search show entries
+fn listing_args() -> Vec<Arg> {
+    vec![
+        Arg::new("TIMESTAMPS")
+            .long("timestamps")
+            .short('t')
+            .help("Show when entries were created, updated, had their password changed and were last used")
+            .num_args(0),
+        Arg::new("SORT")
+            .long("sort")
+            .short('s')
+            .help("Timestamps sort newest first")
+            .value_parser(["id", "name", "created", "updated", "changed", "used"]),
+        Arg::new("TAG")
+            .long("tag")
+            .help("Only entries with this tag (can be repeated)")
+            .action(ArgAction::Append),
+        Arg::new("FOLDER")
+            .long("folder")
+            .help("Only entries in this folder or its subfolders")
+            .num_args(1),
+        Arg::new("TREE")
+            .long("tree")
+            .help("Show entries as a tree of folders")
+            .num_args(0)
+            .conflicts_with("TIMESTAMPS"),
+    ]
+}
+
+fn display_entries(entries: Vec<Entry>, matches: &ArgMatches) {
+    let tags: Vec<String> = matches.get_many::<String>("TAG").unwrap_or_default().cloned().collect();
+    let mut entries = vodka::filter_entries(entries, &tags, matches.get_one::<String>("FOLDER").map(|s| s.as_str()));
+
+    if entries.is_empty() {
+        eprintln!("No entries found!");
+        return;
+    }
+
+    if let Some(key) = matches.get_one::<String>("SORT") {
+        vodka::sort_entries(&mut entries, SortKey::parse(key).unwrap());
+    }
+
+    if matches.get_flag("TREE") {
+        display::display_tree(entries);
+    } else {
+        display::display(entries, matches.get_flag("TIMESTAMPS"));
+    }
+}
+
+// options of the passphrase generator, defaults are taken from [passphrase] in config.toml
+fn passphrase_spec_args() -> Vec<Arg> {
+    vec![
+        Arg::new("PASSPHRASE")
+            .long("passphrase")
+            .help("Generate a passphrase of random words")
+            .num_args(0),
+        Arg::new("WORDS")
+            .long("words")
+            .requires("PASSPHRASE")
+            .value_parser(clap::value_parser!(usize)),
+        Arg::new("SEPARATOR")
+            .long("separator")
+            .requires("PASSPHRASE")
+            .num_args(1),
+        Arg::new("CAPITALIZE")
+            .long("capitalize")
+            .requires("PASSPHRASE")
+            .value_parser(["lower", "upper", "capitalize", "random"]),
+        Arg::new("WITH_DIGIT")
+            .long("with-digit")
+            .requires("PASSPHRASE")
+            .num_args(0),
+        Arg::new("WITH_SYMBOL")
+            .long("with-symbol")
+            .requires("PASSPHRASE")
+            .num_args(0),
+        Arg::new("WORDLIST")
+            .long("wordlist")
+            .help("Wordlist file, one word per line (diceware lists work too)")
+            .requires("PASSPHRASE")
+            .num_args(1),
+    ]
+}
+
+// None unless --passphrase was passed
+fn passphrase_spec_from_matches(matches: &ArgMatches) -> Option<PassphraseSpec> {
+    if !matches.get_flag("PASSPHRASE") {
+        return None;
+    }
+
+    let mut spec = match PassphraseSpec::from_config() {
+        Ok(spec) => spec,
+        Err(e) => {
+            eprintln!("Error in config.toml: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(&words) = matches.get_one::<usize>("WORDS") {
+        spec.words = words;
+    }
+    if let Some(separator) = matches.get_one::<String>("SEPARATOR") {
+        spec.separator = separator.to_string();
+    }
+    if let Some(capitalization) = matches.get_one::<String>("CAPITALIZE") {
+        spec.capitalization = Capitalization::parse(capitalization).unwrap();
+    }
+    if let Some(wordlist) = matches.get_one::<String>("WORDLIST") {
+        spec.wordlist = Some(PathBuf::from(wordlist));
+    }
+    spec.with_digit |= matches.get_flag("WITH_DIGIT");
+    spec.with_symbol |= matches.get_flag("WITH_SYMBOL");
+
+    Some(spec)
+}
+
+fn generate_passphrase(spec: &PassphraseSpec) -> Passphrase {
+    match spec.generate() {
+        Ok(passphrase) => passphrase,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn generate_password(spec: &PasswordSpec) -> String {
+    match spec.generate() {
+        Ok(password) => password,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// the entry given by --id or FULLNAME (strict search). exits unless exactly one entry matches
+fn get_target_entry(matches: &ArgMatches) -> Entry {
+    find_target_entry(matches).0
+}
+
+// like get_target_entry, also telling whether the entry was picked among several matches
+fn find_target_entry(matches: &ArgMatches) -> (Entry, bool) {
+    // search by id
+    if matches.contains_id("ID") {
+        let id = match matches.get_one::<String>("ID").unwrap().parse::<i32>() {
+            Ok(value) => value,
+            Err(e) => {
+                eprintln!("Error while parsing command arguments: {}", e);
+                std::process::exit(1);
+            }
+        };
+        
+        (get_entry_by_id(id), false)
+    } else { // search by fullname
+        let fullname = matches.get_one::<String>("FULLNAME").unwrap().to_string();
+        let (login, name) = vodka::parse_fullname(fullname);
+
+        // strict search
+        match vodka::get_entry(name, login, true) {
+            SearchResult::OneResult(entry) => (entry, false),
+            SearchResult::NoResults => {
+                eprintln!("No entries found!");
+                std::process::exit(1);
+            },
+            SearchResult::ManyResults(entries) => (pick_entry(entries), true)
+        }
+    }
+}
+
+// `vodka delete <id>` from before fullnames were accepted keeps working when no entry has
+// that name. a number that's both a name and another entry's id is refused, --id settles it
+fn find_entry_to_delete(fullname: &str) -> (Entry, bool) {
+    let (login, name) = vodka::parse_fullname(fullname.to_string());
+    let by_id = fullname.parse::<i32>().ok().and_then(store::get_entry_by_id);
+
+    match (vodka::get_entry(name, login, true), by_id) {
+        (SearchResult::NoResults, Some(entry)) => (entry, false),
+        (SearchResult::NoResults, None) => {
+            eprintln!("No entries found!");
+            std::process::exit(1);
+        },
+        (SearchResult::OneResult(entry), None) => (entry, false),
+        (SearchResult::OneResult(entry), Some(by_id)) if by_id.id == entry.id => (entry, false),
+        (SearchResult::ManyResults(entries), None) => (pick_entry(entries), true),
+        (_, Some(by_id)) => {
+            eprintln!(
+                "{} is both the name of an entry and the id of {}@{}. Use `vodka delete --id {}` to delete by id",
+                fullname, by_id.login, by_id.name, by_id.id
+            );
+            std::process::exit(1);
+        },
+    }
+}
+
+fn get_entry_by_id(id: i32) -> Entry {
+    match store::get_entry_by_id(id) {
+        Some(entry) => entry,
+        None => {
+            eprintln!("No such entry found!");
+            std::process::exit(1);
+        }
+    }
+}
+
+// lets the user choose between entries matching a lookup. exits if none is chosen
+fn pick_entry(mut entries: Vec<Entry>) -> Entry {
+    match picker::pick(&entries, "Several entries match, pick one:") {
+        Some(index) => entries.swap_remove(index),
+        None => {
+            eprintln!("No entry selected");
+            std::process::exit(1);
+        }
+    }
+}
+
+// a version from `vodka history`, or the one before the current password if None. exits if there's no such version
+fn get_password_version(entry: &Entry, version: Option<usize>) -> PasswordVersion {
+    let versions = match vodka::get_password_versions(entry) {
+        Ok(versions) => versions,
+        Err(e) => {
+            eprintln!("Error while reading history of entry {}: {}", entry.id, e);
+            std::process::exit(1);
+        }
+    };
+
+    let version = version.unwrap_or(versions.len() - 1);
+    match versions.into_iter().find(|v| v.version == version) {
+        Some(version) => version,
+        None if version == 0 => {
+            eprintln!("Entry {} has no earlier passwords", entry.id);
+            std::process::exit(1);
+        },
+        None => {
+            eprintln!("Entry {} has no version {}, see `vodka history`", entry.id, version);
+            std::process::exit(1);
+        }
+    }
+}
+
+// the named custom field of an entry, exits if there's none
+fn get_field(entry: &Entry, name: &str) -> Field {
+    let fields = match store::get_fields(entry.id) {
+        Ok(fields) => fields,
+        Err(e) => {
+            eprintln!("Error while reading fields of entry {}: {}", entry.id, e);
+            std::process::exit(1);
+        }
+    };
+
+    match fields.into_iter().find(|field| field.name == name) {
+        Some(field) => field,
+        None => {
+            eprintln!("{}@{} has no field '{}'. See `vodka field list`", entry.login, entry.name, name);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn get_field_value(entry: &Entry, field: &Field, master_key: &[u8]) -> String {
+    match field.get_value(master_key) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Error while decrypting field '{}' of entry {}: {}", field.name, entry.id, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// the body of a note, exits if the entry isn't one
+fn get_note_body(entry: &Entry, master_key: &[u8]) -> String {
+    if entry.kind != EntryKind::Note {
+        eprintln!("{}@{} is not a note", entry.login, entry.name);
+        std::process::exit(1);
+    }
+
+    match entry.get_password(master_key) {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("Error while decrypting note {}: {}", entry.id, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn get_ssh_key(entry: &Entry) -> SshKey {
+    match store::get_ssh_key(entry.id) {
+        Ok(Some(key)) => key,
+        Ok(None) => {
+            eprintln!("{} is not an SSH key", entry.name);
+            std::process::exit(1);
+        },
+        Err(e) => {
+            eprintln!("Error while reading SSH key {}: {}", entry.id, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// exits if the entry is a note or an ssh key, which have a body or a private key instead of a password
+fn require_password_entry(entry: &Entry) {
+    match entry.kind {
+        EntryKind::Password => {},
+        EntryKind::Note => {
+            eprintln!("{} is a note, see `vodka note show` and `vodka note edit`", entry.name);
+            std::process::exit(1);
+        },
+        EntryKind::SshKey => {
+            eprintln!("{} is an SSH key, see `vodka ssh public-key` and `vodka ssh-agent`", entry.name);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn copy_password(entry: &Entry, master_key: &[u8]) {
+    require_password_entry(entry);
+
+    match entry.get_password(master_key) {
+        Ok(password) => {
+            vodka::copy_to_clipboard(password);
+            if let Err(e) = store::touch_entry(entry.id) {
+                eprintln!("Warning: failed to record the use of entry {}: {}", entry.id, e);
+            }
+        },
+        Err(e) => {
+            eprintln!("Error while decrypting entry {}: {}", entry.id, e);
+            std::process::exit(1);
+        }
+    }
+}
+
 fn main() -> Result<(), vodka::Error> {
     let matches = cli().get_matches();
     
     match matches.subcommand() {
-        Some(("setup", _)) => {
-            if let Err(e) = setup::setup_vodka() {
+        Some(("setup", matches)) => {
+            if let Err(e) = setup::setup_vodka(passphrase_spec_from_matches(matches)) {
                 eprintln!("Error while setting up vodka: {:?}", e);
                 std::process::exit(1);
             }
         },
         Some(("add", matches)) => {
-            let master_key_sha256 = vodka::unlock();
+            let vault_key = vodka::unlock();
 
             let fullname = matches.get_one::<String>("FULLNAME").unwrap().to_string();
             let (login, name) = vodka::parse_fullname(fullname);
             let mut comment = String::new();
-            let password_unencrypted: String;
             
-            if matches.get_flag("RANDOM") {
-                password_unencrypted = crypto::get_random_password();
+            let password_unencrypted = if let Some(spec) = passphrase_spec_from_matches(matches) {
+                generate_passphrase(&spec).passphrase
+            } else if matches.get_flag("RANDOM") {
+                generate_password(&password_spec_from_matches(matches))
             } else {
-                password_unencrypted = rpassword::prompt_password(
+                let password = rpassword::prompt_password(
                     format!("Create password for {}@{}: ", login, name)
                 ).unwrap();
-            }
+
+                if !vodka::check_password_strength(&password, &[&name, &login], false)
+                    || !vodka::check_password_breaches(&password) {
+                    std::process::exit(1);
+                }
+
+                password
+            };
 
             eprint!("Comments (optional): ");
             std::io::stdin().read_line(&mut comment)?;
 
-            let entry = Entry::new(name, login, password_unencrypted, comment, &master_key_sha256);
+            let mut entry = Entry::new(name, login, password_unencrypted, comment, &vault_key);
+
+            let mut organization = entry.editable();
+            if let Some(folder) = matches.get_one::<String>("FOLDER") {
+                organization.folder = folder.to_string();
+            }
+            organization.tags = matches.get_many::<String>("TAG").unwrap_or_default().cloned().collect();
+            organization.urls = matches.get_many::<String>("URL").unwrap_or_default().cloned().collect();
+            if let Err(e) = entry.apply(organization) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+
             if let Err(e) = vodka::add_entry(entry) {
                 eprintln!("Error while adding password: {:?}", e);
             }
         },
-        Some(("copy", matches)) => {
-            let master_key_sha256 = vodka::unlock();
-            
-            // search by id
-            if matches.contains_id("ID") {
-                let id = match matches.get_one::<String>("ID").unwrap().parse::<i32>() {
-                    Ok(value) => value,
-                    Err(e) => {
-                        eprintln!("Error while parsing command arguments: {}", e);
-                        std::process::exit(1);
-                    }
-                };
-                
-                if let Some(entry) = store::get_entry_by_id(id) {
-                    let password = entry.get_password(&master_key_sha256);
-                    vodka::copy_to_clipboard(password);
-                } else {
-                    eprintln!("No such entry found!");
-                }
-            } else { // search by fullname
-                let fullname = matches.get_one::<String>("FULLNAME").unwrap().to_string();
-                let (login, name) = vodka::parse_fullname(fullname);
+        Some(("generate", matches)) => {
+            let password = match passphrase_spec_from_matches(matches) {
+                Some(spec) => {
+                    let passphrase = generate_passphrase(&spec);
+                    eprintln!("Entropy: {:.1} bits", passphrase.entropy);
+                    passphrase.passphrase
+                },
+                None => generate_password(&password_spec_from_matches(matches))
+            };
 
-                // strict search
-                match vodka::get_entry(name, login, true) {
-                    SearchResult::OneResult(entry) => {
-                        let password = entry.get_password(&master_key_sha256);
-                        vodka::copy_to_clipboard(password);
-                    },
-                    SearchResult::NoResults => { eprintln!("No entries found!"); },
-                    SearchResult::ManyResults(_) => { eprintln!("Several possible entries found. Try searching?"); }
-                }
+            if matches.get_flag("COPY") {
+                vodka::copy_to_clipboard(password);
+            } else {
+                println!("{}", password);
             }
         },
-        Some(("search", matches)) => {
-            vodka::unlock_if_required("search");
-
-            let fullname = matches.get_one::<String>("FULLNAME").unwrap().to_string();
-            let (login, name) = vodka::parse_fullname(fullname);
+        Some(("copy", matches)) => {
+            let vault_key = vodka::unlock();
+            
+            let mut entry = get_target_entry(matches);
+            if let Some(name) = matches.get_one::<String>("FIELD") {
+                let field = get_field(&entry, name);
+                vodka::copy_to_clipboard(get_field_value(&entry, &field, &vault_key));
 
-            match vodka::get_entry(name, login, false) {
-                SearchResult::OneResult(entry) => {
-                    display::display(vec![entry]);
-                },
-                SearchResult::NoResults => { eprintln!("No entries found!") },
-                SearchResult::ManyResults(entries) => {
-                    display::display(entries);
+                if let Err(e) = store::touch_entry(entry.id) {
+                    eprintln!("Warning: failed to record the use of entry {}: {}", entry.id, e);
                 }
+                return Ok(());
             }
+            if let Some(&version) = matches.get_one::<usize>("VERSION") {
+                entry.password = get_password_version(&entry, Some(version)).password;
+            }
+
+            copy_password(&entry, &vault_key);
         },
-        Some(("delete", matches)) => {
-            vodka::unlock_if_required("delete");
-            
-            let id = match matches.get_one::<String>("ID").unwrap().parse::<i32>() {
-                Ok(value) => value,
+        Some(("history", matches)) => {
+            vodka::unlock();
+            let entry = get_target_entry(matches);
+
+            match vodka::get_password_versions(&entry) {
+                Ok(versions) => { display::display_history(&versions); },
                 Err(e) => {
-                    eprintln!("Error while parsing command arguments: {}", e);
+                    eprintln!("Error while reading history of entry {}: {}", entry.id, e);
                     std::process::exit(1);
                 }
-            };
-
-            if let Err(e) = store::delete_entry(id) {
-                eprintln!("Error while deleting entry {}: {:?}", id, e);
             }
         },
-        Some(("list", _)) => {
-            vodka::unlock_if_required("list");
-            display::display_all();
+        Some(("rollback", matches)) => {
+            vodka::unlock();
+            let mut entry = get_target_entry(matches);
+
+            let version = get_password_version(&entry, matches.get_one::<usize>("VERSION").copied());
+            if version.replaced_at.is_none() {
+                eprintln!("Version {} is already the current password", version.version);
+                std::process::exit(1);
+            }
+
+            // the restored password is a change like any other, so the current one goes to the history
+            entry.password = version.password;
+            entry.password_changed_at = Some(vodka::unix_time());
+
+            if let Err(e) = store::update_entry(&entry) {
+                eprintln!("Error while rolling back entry {}: {:?}", entry.id, e);
+                std::process::exit(1);
+            }
+            eprintln!("Restored version {} of {}@{}", version.version, entry.login, entry.name);
         },
-        Some(("export", matches)) => {
-            let master_key_sha256 = vodka::unlock();
+        Some(("edit", matches)) => {
+            let vault_key = vodka::unlock();
+            let mut entry = get_target_entry(matches);
 
-            let file_path = matches.get_one::<String>("FILE").unwrap().as_str();
-            if let Err(e) = transport::export(file_path, &master_key_sha256, false) {
+            if matches.get_flag("EDITOR") {
+                if let Err(e) = vodka::edit_entry_in_editor(&mut entry) {
+                    eprintln!("Error while editing entry: {} (No changes were made)", e);
+                    std::process::exit(1);
+                }
+            } else {
+                let mut fields = entry.editable();
+                let mut changed = false;
+
+                if let Some(name) = matches.get_one::<String>("NAME") {
+                    fields.name = name.to_string();
+                    changed = true;
+                }
+                if let Some(login) = matches.get_one::<String>("LOGIN") {
+                    fields.login = login.to_string();
+                    changed = true;
+                }
+                if let Some(comment) = matches.get_one::<String>("COMMENT") {
+                    fields.comment = comment.to_string();
+                    changed = true;
+                }
+                if let Some(folder) = matches.get_one::<String>("FOLDER") {
+                    fields.folder = folder.to_string();
+                    changed = true;
+                }
+
+                if let Err(e) = entry.apply(fields) {
+                    eprintln!("Error: {} (No changes were made)", e);
+                    std::process::exit(1);
+                }
+
+                if matches.get_flag("RANDOM") || matches.get_flag("PASSWORD") {
+                    require_password_entry(&entry);
+                }
+
+                if matches.get_flag("RANDOM") {
+                    entry.set_password(generate_password(&PasswordSpec::from_config()), &vault_key);
+                    changed = true;
+                } else if matches.get_flag("PASSWORD") {
+                    let password = rpassword::prompt_password(
+                        format!("New password for {}@{}: ", entry.login, entry.name)
+                    ).unwrap();
+
+                    if !vodka::check_password_strength(&password, &[&entry.name, &entry.login], false) {
+                        eprintln!("(No changes were made)");
+                        std::process::exit(1);
+                    }
+
+                    entry.set_password(password, &vault_key);
+                    changed = true;
+                }
+
+                if !changed {
+                    eprintln!("Nothing to edit. Pass --name, --login, --comment, --folder, --password, --random or --editor");
+                    std::process::exit(1);
+                }
+            }
+
+            if let Err(e) = store::update_entry(&entry) {
+                eprintln!("Error while editing entry {}: {:?}", entry.id, e);
+                std::process::exit(1);
+            }
+        },
+        Some(("otp", matches)) => {
+            let vault_key = vodka::unlock();
+            let mut entry = get_target_entry(matches);
+
+            if matches.get_flag("SET") {
+                let input = rpassword::prompt_password(
+                    format!("OTP secret or otpauth:// uri for {}@{}: ", entry.login, entry.name)
+                ).unwrap();
+
+                let otp = if input.trim().starts_with("otpauth://") {
+                    Otp::from_uri(&input)
+                } else {
+                    let algorithm = otp::Algorithm::parse(matches.get_one::<String>("ALGORITHM").unwrap())?;
+                    let digits = *matches.get_one::<u32>("DIGITS").unwrap();
+                    let kind = match matches.get_one::<u64>("COUNTER") {
+                        Some(&counter) => otp::Kind::Hotp { counter },
+                        None => otp::Kind::Totp { period: *matches.get_one::<u64>("PERIOD").unwrap() },
+                    };
+
+                    Otp::new(&input, algorithm, digits, kind)
+                };
+
+                match otp {
+                    Ok(otp) => entry.set_otp(Some(&otp), &vault_key),
+                    Err(e) => {
+                        eprintln!("Error: {} (No changes were made)", e);
+                        std::process::exit(1);
+                    }
+                }
+            } else if matches.get_flag("REMOVE") {
+                entry.set_otp(None, &vault_key);
+            } else {
+                let mut otp = match entry.get_otp(&vault_key) {
+                    Ok(Some(otp)) => otp,
+                    Ok(None) => {
+                        eprintln!("{}@{} has no OTP secret. Add one with `vodka otp --set`", entry.login, entry.name);
+                        std::process::exit(1);
+                    },
+                    Err(e) => {
+                        eprintln!("Error while decrypting OTP secret of entry {}: {}", entry.id, e);
+                        std::process::exit(1);
+                    }
+                };
+
+                let code = otp.generate();
+
+                if matches.get_flag("COPY") {
+                    vodka::copy_to_clipboard(code);
+                } else {
+                    println!("{}", code);
+                }
+
+                if let Some(remaining) = otp.remaining_seconds() {
+                    eprintln!("Valid for {}s", remaining);
+                }
+
+                // an HOTP code can only be used once, move on to the next counter
+                if let otp::Kind::Hotp { .. } = otp.kind {
+                    otp.advance();
+                    entry.set_otp(Some(&otp), &vault_key);
+                } else {
+                    return Ok(());
+                }
+            }
+
+            if let Err(e) = store::update_entry(&entry) {
+                eprintln!("Error while updating entry {}: {:?}", entry.id, e);
+                std::process::exit(1);
+            }
+        },
+        Some(("field", matches)) => {
+            let vault_key = vodka::unlock();
+
+            match matches.subcommand() {
+                Some(("set", matches)) => {
+                    let entry = get_target_entry(matches);
+                    let name = matches.get_one::<String>("FIELD").unwrap().to_string();
+                    let secret = matches.get_flag("SECRET");
+
+                    let value = if let Some(value) = matches.get_one::<String>("VALUE") {
+                        value.to_string()
+                    } else if secret {
+                        rpassword::prompt_password(format!("Value of {} for {}@{}: ", name, entry.login, entry.name)).unwrap()
+                    } else {
+                        let mut value = String::new();
+                        eprint!("Value of {} for {}@{}: ", name, entry.login, entry.name);
+                        std::io::stdin().read_line(&mut value)?;
+                        value.trim_end_matches(['\r', '\n']).to_string()
+                    };
+
+                    let result = Field::new(name, value, secret, &vault_key)
+                        .and_then(|field| store::set_field(entry.id, &field));
+                    if let Err(e) = result {
+                        eprintln!("Error while setting field of entry {}: {}", entry.id, e);
+                        std::process::exit(1);
+                    }
+                },
+                Some(("get", matches)) => {
+                    let entry = get_target_entry(matches);
+                    let field = get_field(&entry, matches.get_one::<String>("FIELD").unwrap());
+
+                    println!("{}", get_field_value(&entry, &field, &vault_key));
+                },
+                Some(("rm", matches)) => {
+                    let entry = get_target_entry(matches);
+                    let name = matches.get_one::<String>("FIELD").unwrap();
+
+                    match store::remove_field(entry.id, name) {
+                        Ok(true) => {},
+                        Ok(false) => {
+                            eprintln!("{}@{} has no field '{}'", entry.login, entry.name, name);
+                            std::process::exit(1);
+                        },
+                        Err(e) => {
+                            eprintln!("Error while removing field of entry {}: {}", entry.id, e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                Some(("list", matches)) => {
+                    let entry = get_target_entry(matches);
+
+                    match store::get_fields(entry.id) {
+                        Ok(fields) if fields.is_empty() => eprintln!("{}@{} has no fields", entry.login, entry.name),
+                        Ok(fields) => { display::display_fields(&fields); },
+                        Err(e) => {
+                            eprintln!("Error while reading fields of entry {}: {}", entry.id, e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                _ => unreachable!(),
+            }
+        },
+        Some(("note", matches)) => {
+            let vault_key = vodka::unlock();
+
+            match matches.subcommand() {
+                Some(("add", matches)) => {
+                    let name = matches.get_one::<String>("NAME").unwrap().to_string();
+
+                    let body = if std::io::stdin().is_terminal() {
+                        vodka::edit_in_editor("", "txt")
+                    } else {
+                        let mut body = String::new();
+                        std::io::stdin().read_to_string(&mut body).map(|_| body).map_err(vodka::Error::from)
+                    };
+                    let body = match body {
+                        Ok(body) if body.trim().is_empty() => {
+                            eprintln!("Error: the note is empty (No changes were made)");
+                            std::process::exit(1);
+                        },
+                        Ok(body) => body,
+                        Err(e) => {
+                            eprintln!("Error while reading note: {}", e);
+                            std::process::exit(1);
+                        }
+                    };
+
+                    let mut entry = Entry::new_note(name, body, &vault_key);
+
+                    let mut organization = entry.editable();
+                    if let Some(folder) = matches.get_one::<String>("FOLDER") {
+                        organization.folder = folder.to_string();
+                    }
+                    organization.tags = matches.get_many::<String>("TAG").unwrap_or_default().cloned().collect();
+
+                    if let Err(e) = entry.apply(organization).and_then(|_| vodka::add_entry(entry)) {
+                        eprintln!("Error while adding note: {}", e);
+                        std::process::exit(1);
+                    }
+                },
+                Some(("show", matches)) => {
+                    let entry = get_target_entry(matches);
+                    let body = get_note_body(&entry, &vault_key);
+
+                    print!("{}", body);
+                    if !body.ends_with('\n') {
+                        println!();
+                    }
+
+                    if let Err(e) = store::touch_entry(entry.id) {
+                        eprintln!("Warning: failed to record the use of entry {}: {}", entry.id, e);
+                    }
+                },
+                Some(("edit", matches)) => {
+                    let mut entry = get_target_entry(matches);
+                    let body = get_note_body(&entry, &vault_key);
+
+                    let edited = match vodka::edit_in_editor(&body, "txt") {
+                        Ok(edited) => edited,
+                        Err(e) => {
+                            eprintln!("Error while editing note: {} (No changes were made)", e);
+                            std::process::exit(1);
+                        }
+                    };
+                    if edited == body {
+                        eprintln!("No changes were made");
+                        return Ok(());
+                    }
+                    if edited.trim().is_empty() {
+                        eprintln!("Error: the note is empty (No changes were made)");
+                        std::process::exit(1);
+                    }
+
+                    entry.set_password(edited, &vault_key);
+                    if let Err(e) = store::update_entry(&entry) {
+                        eprintln!("Error while editing note {}: {}", entry.id, e);
+                        std::process::exit(1);
+                    }
+                },
+                _ => unreachable!(),
+            }
+        },
+        Some(("attach", matches)) => {
+            let vault_key = vodka::unlock();
+
+            match matches.subcommand() {
+                Some(("add", matches)) => {
+                    let entry = get_target_entry(matches);
+                    let path = vodka::get_absolute_path(matches.get_one::<String>("FILE").unwrap());
+                    let name = matches.get_one::<String>("NAME").map(|name| name.as_str());
+
+                    match attachments::add_file(&entry, &path, name, &vault_key) {
+                        Ok(name) => eprintln!("Attached {} to {}@{}", name, entry.login, entry.name),
+                        Err(e) => {
+                            eprintln!("Error while attaching {}: {}", path.display(), e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                Some(("ls", matches)) => {
+                    let entry = get_target_entry(matches);
+
+                    match store::get_attachments(entry.id) {
+                        Ok(attachments) if attachments.is_empty() => eprintln!("{}@{} has no attachments", entry.login, entry.name),
+                        Ok(attachments) => { display::display_attachments(&attachments); },
+                        Err(e) => {
+                            eprintln!("Error while reading attachments of entry {}: {}", entry.id, e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                Some(("get", matches)) => {
+                    let entry = get_target_entry(matches);
+                    let name = matches.get_one::<String>("ATTACHMENT").unwrap();
+
+                    let attachment = match store::get_attachments(entry.id) {
+                        Ok(attachments) => attachments.into_iter().find(|attachment| attachment.name == *name),
+                        Err(e) => {
+                            eprintln!("Error while reading attachments of entry {}: {}", entry.id, e);
+                            std::process::exit(1);
+                        }
+                    };
+                    let Some(attachment) = attachment else {
+                        eprintln!("{}@{} has no attachment '{}'. See `vodka attach ls`", entry.login, entry.name, name);
+                        std::process::exit(1);
+                    };
+
+                    let content = match attachments::read(&attachment, &vault_key) {
+                        Ok(content) => content,
+                        Err(e) => {
+                            eprintln!("Error while decrypting attachment '{}' of entry {}: {}", name, entry.id, e);
+                            std::process::exit(1);
+                        }
+                    };
+
+                    let result = match matches.get_one::<String>("OUTPUT") {
+                        Some(output) => {
+                            let path = vodka::get_absolute_path(output);
+                            if path.exists() && !vodka::ask_for_confirmation(format!("{} already exists. This will overwrite the existing file.", path.display())) {
+                                std::process::exit(1);
+                            }
+                            attachments::write_file(&path, &content)
+                        },
+                        None => std::io::stdout().write_all(&content).map_err(vodka::Error::from),
+                    };
+                    if let Err(e) = result {
+                        eprintln!("Error while writing attachment '{}': {}", name, e);
+                        std::process::exit(1);
+                    }
+
+                    if let Err(e) = store::touch_entry(entry.id) {
+                        eprintln!("Warning: failed to record the use of entry {}: {}", entry.id, e);
+                    }
+                },
+                Some(("rm", matches)) => {
+                    let entry = get_target_entry(matches);
+                    let name = matches.get_one::<String>("ATTACHMENT").unwrap();
+
+                    match store::remove_attachment(entry.id, name) {
+                        Ok(true) => {},
+                        Ok(false) => {
+                            eprintln!("{}@{} has no attachment '{}'", entry.login, entry.name, name);
+                            std::process::exit(1);
+                        },
+                        Err(e) => {
+                            eprintln!("Error while removing attachment of entry {}: {}", entry.id, e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                _ => unreachable!(),
+            }
+        },
+        Some(("ssh", matches)) => {
+            let vault_key = vodka::unlock();
+
+            match matches.subcommand() {
+                Some(("generate", matches)) => {
+                    let name = matches.get_one::<String>("NAME").unwrap().to_string();
+                    let key_type = ssh::KeyType::parse(matches.get_one::<String>("TYPE").unwrap()).unwrap();
+                    let comment = matches.get_one::<String>("COMMENT").cloned().unwrap_or_else(|| name.clone());
+
+                    let result = ssh::generate(key_type, matches.get_one::<usize>("BITS").copied(), &comment)
+                        .and_then(|key| ssh::add_key(name, &key, matches.get_flag("CONFIRM"), &vault_key).map(|_| key));
+                    match result {
+                        Ok(key) => println!("{}", key.public_key().to_openssh().unwrap_or_default()),
+                        Err(e) => {
+                            eprintln!("Error while generating SSH key: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                Some(("import", matches)) => {
+                    let path = vodka::get_absolute_path(matches.get_one::<String>("FILE").unwrap());
+                    let name = matches.get_one::<String>("NAME").unwrap().to_string();
+
+                    let key = std::fs::read_to_string(&path)
+                        .map_err(vodka::Error::from)
+                        .and_then(|pem| ssh::parse_private_key(&pem))
+                        .and_then(|key| if key.is_encrypted() {
+                            let passphrase = rpassword::prompt_password(format!("Enter passphrase for {}: ", path.display())).unwrap();
+                            ssh::decrypt(&key, &passphrase)
+                        } else {
+                            Ok(key)
+                        });
+
+                    match key.and_then(|key| ssh::add_key(name, &key, matches.get_flag("CONFIRM"), &vault_key)) {
+                        Ok(id) => eprintln!("Imported {} as entry {}", path.display(), id),
+                        Err(e) => {
+                            eprintln!("Error while importing {}: {}", path.display(), e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                Some(("list", _)) => {
+                    match store::get_ssh_keys() {
+                        Ok(keys) if keys.is_empty() => eprintln!("No SSH keys, add one with `vodka ssh generate` or `vodka ssh import`"),
+                        Ok(keys) => { display::display_ssh_keys(&keys); },
+                        Err(e) => {
+                            eprintln!("Error while reading SSH keys: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                Some(("public-key", matches)) => {
+                    println!("{}", get_ssh_key(&get_target_entry(matches)).public_key);
+                },
+                Some(("confirm", matches)) => {
+                    let entry = get_target_entry(matches);
+                    let confirm = match matches.get_one::<String>("STATE").unwrap().as_str() {
+                        "on" => true,
+                        "off" => false,
+                        state => {
+                            eprintln!("Error: expected on or off, not {}", state);
+                            std::process::exit(1);
+                        }
+                    };
+
+                    get_ssh_key(&entry);
+                    if let Err(e) = store::set_ssh_key_confirm(entry.id, confirm) {
+                        eprintln!("Error while updating SSH key {}: {}", entry.id, e);
+                        std::process::exit(1);
+                    }
+                },
+                _ => unreachable!(),
+            }
+        },
+        Some(("tag", matches)) => {
+            vodka::unlock();
+
+            match matches.subcommand() {
+                Some((action @ ("add" | "rm"), matches)) => {
+                    let mut entry = get_target_entry(matches);
+                    let tag = match vodka::parse_tag(matches.get_one::<String>("TAG").unwrap()) {
+                        Ok(tag) => tag,
+                        Err(e) => {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(1);
+                        }
+                    };
+
+                    let has_tag = entry.tags.contains(&tag);
+                    if action == "add" && has_tag {
+                        eprintln!("{}@{} is already tagged {}", entry.login, entry.name, tag);
+                        return Ok(());
+                    }
+                    if action == "rm" && !has_tag {
+                        eprintln!("{}@{} isn't tagged {}", entry.login, entry.name, tag);
+                        std::process::exit(1);
+                    }
+
+                    let mut fields = entry.editable();
+                    if action == "add" {
+                        fields.tags.push(tag);
+                    } else {
+                        fields.tags.retain(|t| *t != tag);
+                    }
+
+                    if let Err(e) = entry.apply(fields).and_then(|_| store::update_entry(&entry)) {
+                        eprintln!("Error while tagging entry {}: {}", entry.id, e);
+                        std::process::exit(1);
+                    }
+                },
+                Some(("list", _)) => {
+                    match store::get_tags() {
+                        Ok(tags) if tags.is_empty() => eprintln!("No tags yet. Add some with `vodka tag add`"),
+                        Ok(tags) => { display::display_tags(&tags); },
+                        Err(e) => {
+                            eprintln!("Error while reading tags: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                },
+                _ => unreachable!(),
+            }
+        },
+        Some(("url", matches)) => {
+            vodka::unlock();
+
+            let (action, matches) = matches.subcommand().unwrap();
+            let mut entry = get_target_entry(matches);
+            let url = match urls::normalize(matches.get_one::<String>("URL").unwrap()) {
+                Ok(url) => url,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            let mut fields = entry.editable();
+            if action == "add" {
+                fields.urls.push(url);
+            } else if fields.urls.contains(&url) {
+                fields.urls.retain(|u| *u != url);
+            } else {
+                eprintln!("{}@{} has no url {}", entry.login, entry.name, url);
+                std::process::exit(1);
+            }
+
+            if let Err(e) = entry.apply(fields).and_then(|_| store::update_entry(&entry)) {
+                eprintln!("Error while updating urls of entry {}: {}", entry.id, e);
+                std::process::exit(1);
+            }
+        },
+        Some(("find-url", matches)) => {
+            let vault_key = vodka::unlock();
+
+            let mode = UrlMatch::parse(matches.get_one::<String>("MATCH").unwrap()).unwrap();
+            let result = match urls::find_by_url(store::get_all_rows(), matches.get_one::<String>("URL").unwrap(), mode) {
+                Ok(result) => result,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            match result {
+                SearchResult::OneResult(entry) => {
+                    if matches.get_flag("COPY") {
+                        copy_password(&entry, &vault_key);
+                    } else {
+                        display::display(vec![entry], false);
+                    }
+                },
+                SearchResult::NoResults => {
+                    eprintln!("No entries found!");
+                    std::process::exit(1);
+                },
+                SearchResult::ManyResults(entries) => {
+                    if matches.get_flag("COPY") {
+                        copy_password(&pick_entry(entries), &vault_key);
+                    } else {
+                        display::display(entries, false);
+                    }
+                }
+            }
+        },
+        Some(("search", matches)) => {
+            vodka::unlock_if_required("search");
+
+            let query = matches.get_one::<String>("QUERY").unwrap().to_string();
+
+            if matches.get_flag("TEXT") {
+                match store::search_entries_text(&query) {
+                    Ok(entries) => display_entries(entries, matches),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return Ok(());
+            }
+
+            if !matches.get_flag("EXACT") {
+                let mode = if matches.get_flag("REGEX") {
+                    SearchMode::Regex
+                } else if matches.get_flag("SUBSTRING") {
+                    SearchMode::Substring
+                } else {
+                    SearchMode::Fuzzy
+                };
+
+                let result = store::get_plain_field_values()
+                    .and_then(|plain_fields| search::search(store::get_all_rows(), &query, mode, &plain_fields));
+                match result {
+                    Ok(entries) => display_entries(entries, matches),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return Ok(());
+            }
+
+            let (login, name) = vodka::parse_fullname(query);
+
+            match vodka::get_entry(name, login, false) {
+                SearchResult::OneResult(entry) => {
+                    display_entries(vec![entry], matches);
+                },
+                SearchResult::NoResults => { eprintln!("No entries found!") },
+                SearchResult::ManyResults(entries) => {
+                    display_entries(entries, matches);
+                }
+            }
+        },
+        Some(("delete", matches)) => {
+            vodka::unlock_if_required("delete");
+
+            let (entry, picked) = match matches.get_one::<String>("FULLNAME") {
+                Some(fullname) => find_entry_to_delete(fullname),
+                None => find_target_entry(matches),
+            };
+
+            // a pick among several matches is easy to get wrong
+            if picked && !vodka::ask_for_confirmation(format!("This will delete {}@{} (id {}).", entry.login, entry.name, entry.id)) {
+                std::process::exit(1);
+            }
+
+            if let Err(e) = store::delete_entry(entry.id) {
+                eprintln!("Error while deleting entry {}: {:?}", entry.id, e);
+                std::process::exit(1);
+            }
+        },
+        Some(("list", matches)) => {
+            vodka::unlock_if_required("list");
+            display_entries(store::get_all_rows(), matches);
+        },
+        Some(("audit", matches)) => {
+            let vault_key = vodka::unlock();
+
+            let mut options = AuditOptions::from_config();
+            if let Some(&min_score) = matches.get_one::<u8>("MIN_SCORE") {
+                options.min_score = min_score;
+            }
+            if let Some(&max_age) = matches.get_one::<u64>("MAX_AGE") {
+                options.max_age_days = max_age;
+            }
+            if let Some(breach_db) = matches.get_one::<String>("BREACH_DB") {
+                options.breach_db = Some(vodka::get_absolute_path(breach_db));
+            }
+
+            let report = match audit::audit(&store::get_all_rows(), &vault_key, &options) {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Error while auditing: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            if matches.get_flag("JSON") {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            } else if report.has_problems() {
+                display::display_audit(&report);
+            } else {
+                eprintln!("No problems found");
+            }
+
+            if report.has_problems() {
+                std::process::exit(1);
+            }
+        },
+        Some(("breach-index", matches)) => {
+            let file = vodka::get_absolute_path(matches.get_one::<String>("FILE").unwrap());
+            // lookups only ever look for the index next to the file
+            let output = breach::index_path(&file);
+
+            match breach::build_index(&file, &output) {
+                Ok(count) => eprintln!("Indexed {} hashes into {}", count, output.display()),
+                Err(e) => {
+                    eprintln!("Error while indexing {}: {}", file.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Some(("export", matches)) => {
+            let vault_key = vodka::unlock();
+
+            let file_path = matches.get_one::<String>("FILE").unwrap().as_str();
+            if let Err(e) = transport::export(file_path, &vault_key, false) {
                 match e {
                     vodka::Error::ExportFileExists(_) => {
                         let confirmed = vodka::ask_for_confirmation(
@@ -196,7 +1791,7 @@ fn main() -> Result<(), vodka::Error> {
                         );
 
                         if confirmed {
-                            transport::export(file_path, &master_key_sha256, true)?;
+                            transport::export(file_path, &vault_key, true)?;
                         }
                     },
                     _ => { eprintln!("Error during exporting: {:?}", e) },
@@ -204,10 +1799,10 @@ fn main() -> Result<(), vodka::Error> {
             }
         },
         Some(("import", matches)) => {
-            let master_key_sha256 = vodka::unlock();
+            let vault_key = vodka::unlock();
 
             let file_path = matches.get_one::<String>("FILE").unwrap().as_str();
-            if let Err(e) = transport::import(file_path, &master_key_sha256, false) {
+            if let Err(e) = transport::import(file_path, &vault_key, false) {
                 match e {
                     vodka::Error::ImportFileExists(_) => {
                         let confirmed = vodka::ask_for_confirmation(
@@ -215,23 +1810,29 @@ fn main() -> Result<(), vodka::Error> {
                         );
                         
                         if confirmed {
-                            transport::import(file_path, &master_key_sha256, true)?;
+                            transport::import(file_path, &vault_key, true)?;
                         }
                     },
                     _ => { eprintln!("Error during importing: {:?}", e) },
                 }
             }
         },
-        Some(("change-master", _)) => {
-            vodka::unlock_with_prompt("Enter old master key: ");
+        Some(("change-master", matches)) => {
+            let vault_key = vodka::unlock_with_prompt("Enter old master key: ");
+            let passphrase = passphrase_spec_from_matches(matches);
             
-            let new_master_key = rpassword::prompt_password("Enter new master key: ").unwrap();
-            if new_master_key != rpassword::prompt_password("Confirm new master key: ").unwrap() {
-                eprintln!("Error: Please enter the same master key! (No changes were made)");
+            let new_master_key = match setup::prompt_new_master_key("new master key", passphrase.as_ref())? {
+                Some(new_master_key) => new_master_key,
+                None => {
+                    eprintln!("Error: Please enter the same master key! (No changes were made)");
+                    std::process::exit(1);
+                }
+            };
+
+            if let Err(e) = vodka::change_master(&vault_key, new_master_key) {
+                eprintln!("Error while changing master key: {} (No changes were made)", e);
                 std::process::exit(1);
             }
-
-            setup::set_master(new_master_key, true)?;
         },
         Some(("erase", _)) => {
             let entry_count = store::get_all_rows().len();
@@ -245,6 +1846,102 @@ fn main() -> Result<(), vodka::Error> {
             
             store::erase_all()?;
         },
+        #[cfg(unix)]
+        Some(("agent", matches)) => {
+            use vodka::agent;
+
+            let result = if matches.get_flag("STDIN_KEY") { // spawned by `vodka agent`
+                let mut hex_key = String::new();
+                std::io::stdin().read_line(&mut hex_key)?;
+
+                match crypto::from_hex(hex_key.trim()) {
+                    Some(key) => agent::serve(key),
+                    None => Err(vodka::Error::AgentFailed(String::from("invalid key"))),
+                }
+            } else {
+                if agent::get_key().is_some() {
+                    eprintln!("An agent is already running. Stop it with `vodka lock`");
+                    std::process::exit(1);
+                }
+
+                let vault_key = vodka::unlock_with_prompt("Enter master key: ");
+
+                if matches.get_flag("FOREGROUND") {
+                    agent::serve(vault_key)
+                } else {
+                    agent::spawn(vault_key).map(|pid| eprintln!("Agent started (pid {})", pid))
+                }
+            };
+
+            if let Err(e) = result {
+                eprintln!("Error while running agent: {}", e);
+                std::process::exit(1);
+            }
+        },
+        #[cfg(unix)]
+        Some(("ssh-agent", matches)) => {
+            use vodka::ssh_agent;
+
+            let result = if matches.get_flag("STDIN_KEY") { // spawned by `vodka ssh-agent`
+                let mut hex_key = String::new();
+                std::io::stdin().read_line(&mut hex_key)?;
+
+                match crypto::from_hex(hex_key.trim()) {
+                    Some(key) => ssh_agent::serve(key, false),
+                    None => Err(vodka::Error::AgentFailed(String::from("invalid key"))),
+                }
+            } else {
+                if ssh_agent::is_running() {
+                    eprintln!("An ssh-agent is already running. Stop it with `vodka lock`");
+                    std::process::exit(1);
+                }
+
+                let vault_key = vodka::unlock();
+                let export = format!("SSH_AUTH_SOCK={}; export SSH_AUTH_SOCK;", ssh_agent::get_socket_path().display());
+
+                if matches.get_flag("FOREGROUND") {
+                    println!("{}", export);
+                    ssh_agent::serve(vault_key, true)
+                } else {
+                    ssh_agent::spawn(vault_key).map(|pid| {
+                        eprintln!("SSH agent started (pid {})", pid);
+                        println!("{}", export);
+                    })
+                }
+            };
+
+            if let Err(e) = result {
+                eprintln!("Error while running ssh-agent: {}", e);
+                std::process::exit(1);
+            }
+        },
+        #[cfg(unix)]
+        Some(("lock", _)) => {
+            // one failing to stop must not keep the other running
+            let results = [vodka::agent::lock(), vodka::ssh_agent::stop()];
+
+            for e in results.iter().filter_map(|result| result.as_ref().err()) {
+                eprintln!("Error while locking: {}", e);
+            }
+            if results.iter().any(|result| result.is_err()) {
+                std::process::exit(1);
+            }
+
+            if results.iter().any(|result| matches!(result, Ok(true))) {
+                eprintln!("Locked");
+            } else {
+                eprintln!("No agent running");
+            }
+        },
+        #[cfg(not(unix))]
+        Some(("agent", _)) | Some(("ssh-agent", _)) | Some(("lock", _)) => {
+            eprintln!("Error: the agent is only supported on unix");
+            std::process::exit(1);
+        },
+        Some(("clear-clipboard", matches)) => {
+            let timeout = *matches.get_one::<u64>("TIMEOUT").unwrap();
+            vodka::clear_clipboard_after(timeout)?;
+        },
         Some(("config", matches)) => {
             vodka::unlock_if_required("config");
 
@@ -275,7 +1972,7 @@ fn main() -> Result<(), vodka::Error> {
             match default_cmd.as_str().trim_matches('"') {
                 "list" => {
                     vodka::unlock_if_required("list");
-                    display::display_all();
+                    display::display_all(false);
                 },
                 _ => { // help
                     cli().print_help()?;
@@ -0,0 +1,418 @@
+use crate::{unix_time, Error};
+use rusqlite::{Connection, Transaction};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// schema changes of the cellar, in order. a cellar's `PRAGMA user_version` is the number of
+// migrations already applied to it, so new ones must be appended, never inserted or edited
+type Migration = fn(&Transaction) -> Result<(), Error>;
+
+const MIGRATIONS: &[Migration] = &[
+    initial_schema,
+    custom_fields,
+    tags_and_folders,
+    entry_urls,
+    full_text_index,
+    entry_kinds,
+    attachments,
+    ssh_keys,
+];
+
+// schema version written by this version of vodka
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+pub fn current_version(connection: &Connection) -> Result<u32, Error> {
+    Ok(connection.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+// brings the cellar up to date. each migration runs in its own transaction together with
+// the version bump, so an interrupted upgrade leaves the cellar at the last completed version
+pub fn migrate(connection: &mut Connection, cellar_path: &Path) -> Result<(), Error> {
+    let version = current_version(connection)?;
+
+    if version > latest_version() {
+        return Err(Error::CellarTooNew(version, latest_version()));
+    }
+    if version == latest_version() {
+        return Ok(());
+    }
+
+    if has_tables(connection)? {
+        let backup_path = backup(cellar_path, version)?;
+        eprintln!("Upgrading cellar from version {} to {} (backup at {})", version, latest_version(), backup_path.display());
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let transaction = connection.transaction()?;
+        migration(&transaction)?;
+        transaction.pragma_update(None, "user_version", index as u32 + 1)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+fn has_tables(connection: &Connection) -> Result<bool, Error> {
+    let count: i32 = connection.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'", [], |row| row.get(0))?;
+    Ok(count > 0)
+}
+
+// copy of the cellar as it was before upgrading, next to it: cellar.sqlite.v<version>-<time>.bak
+fn backup(cellar_path: &Path, version: u32) -> Result<PathBuf, Error> {
+    let mut backup_path = cellar_path.as_os_str().to_owned();
+    backup_path.push(format!(".v{}-{}.bak", version, unix_time()));
+    let backup_path = PathBuf::from(backup_path);
+
+    fs::copy(cellar_path, &backup_path)?;
+
+    Ok(backup_path)
+}
+
+// 1: the schema as it was before cellars were versioned. unversioned cellars may have any part
+// of it already, depending on the vodka version that last opened them, so every step checks first
+fn initial_schema(transaction: &Transaction) -> Result<(), Error> {
+    transaction.execute(
+        "CREATE TABLE IF NOT EXISTS passwords (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            login TEXT NOT NULL,
+            password BLOB NOT NULL,
+            comment TEXT
+        )",
+        [],
+    )?;
+
+    transaction.execute(
+        "CREATE TABLE IF NOT EXISTS vault (
+            key TEXT PRIMARY KEY,
+            value BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    // replaced passwords, still encrypted with the data key
+    transaction.execute(
+        "CREATE TABLE IF NOT EXISTS password_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entry_id INTEGER NOT NULL,
+            password BLOB NOT NULL,
+            set_at INTEGER,
+            replaced_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    add_column_if_missing(transaction, "passwords", "otp", "BLOB")?;
+    // timestamps are unknown (NULL) for older entries
+    add_column_if_missing(transaction, "passwords", "created_at", "INTEGER")?;
+    add_column_if_missing(transaction, "passwords", "updated_at", "INTEGER")?;
+    add_column_if_missing(transaction, "passwords", "password_changed_at", "INTEGER")?;
+    add_column_if_missing(transaction, "passwords", "last_used_at", "INTEGER")?;
+
+    Ok(())
+}
+
+// 2: custom fields of entries, see `vodka field`
+fn custom_fields(transaction: &Transaction) -> Result<(), Error> {
+    transaction.execute(
+        "CREATE TABLE fields (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entry_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            value BLOB NOT NULL,
+            secret INTEGER NOT NULL,
+            UNIQUE (entry_id, name)
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+// 3: a folder path per entry and tags shared between entries
+fn tags_and_folders(transaction: &Transaction) -> Result<(), Error> {
+    transaction.execute_batch(
+        "ALTER TABLE passwords ADD COLUMN folder TEXT NOT NULL DEFAULT '';
+        CREATE TABLE tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE entry_tags (
+            entry_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (entry_id, tag_id)
+        );"
+    )?;
+
+    Ok(())
+}
+
+// 4: urls of entries, see `vodka find-url`
+fn entry_urls(transaction: &Transaction) -> Result<(), Error> {
+    transaction.execute(
+        "CREATE TABLE entry_urls (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entry_id INTEGER NOT NULL,
+            url TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+// 5: FTS5 index over the plain text of entries, see `vodka search --text`. its rowid is the
+// entry id. triggers rebuild the row of an entry whenever it, its tags or its urls change, so
+// the index is always in sync. only plaintext columns are indexed, never passwords or fields
+fn full_text_index(transaction: &Transaction) -> Result<(), Error> {
+    transaction.execute(
+        "CREATE VIRTUAL TABLE entries_fts USING fts5(
+            name, login, comment, tags, urls,
+            tokenize = 'unicode61 remove_diacritics 2'
+        )",
+        [],
+    )?;
+
+    let triggers = [
+        ("passwords_fts_insert", "AFTER INSERT ON passwords", "new.id"),
+        ("passwords_fts_update", "AFTER UPDATE OF name, login, comment ON passwords", "new.id"),
+        ("passwords_fts_delete", "AFTER DELETE ON passwords", "old.id"),
+        ("entry_tags_fts_insert", "AFTER INSERT ON entry_tags", "new.entry_id"),
+        ("entry_tags_fts_delete", "AFTER DELETE ON entry_tags", "old.entry_id"),
+        ("entry_urls_fts_insert", "AFTER INSERT ON entry_urls", "new.entry_id"),
+        ("entry_urls_fts_delete", "AFTER DELETE ON entry_urls", "old.entry_id"),
+    ];
+    for (name, event, entry_id) in triggers {
+        transaction.execute_batch(&format!(
+            "CREATE TRIGGER {name} {event} BEGIN
+                DELETE FROM entries_fts WHERE rowid = {entry_id};
+                {insert};
+            END",
+            name = name, event = event, entry_id = entry_id,
+            insert = index_entries_sql(&format!("passwords.id = {}", entry_id))
+        ))?;
+    }
+
+    // entries already in the cellar
+    transaction.execute(&index_entries_sql("1"), [])?;
+
+    Ok(())
+}
+
+// statement adding the name, login, comment, tags and urls of the entries matching `condition`
+// to the index. tags and urls are space-separated, the tokenizer splits them further
+fn index_entries_sql(condition: &str) -> String {
+    format!(
+        "INSERT INTO entries_fts (rowid, name, login, comment, tags, urls)
+            SELECT id, name, login, comment,
+                (SELECT group_concat(tags.name, ' ') FROM entry_tags JOIN tags ON tags.id = entry_tags.tag_id WHERE entry_tags.entry_id = passwords.id),
+                (SELECT group_concat(url, ' ') FROM entry_urls WHERE entry_urls.entry_id = passwords.id)
+            FROM passwords WHERE {}",
+        condition
+    )
+}
+
+// 6: entries are passwords or notes, see `vodka note`
+fn entry_kinds(transaction: &Transaction) -> Result<(), Error> {
+    transaction.execute("ALTER TABLE passwords ADD COLUMN kind TEXT NOT NULL DEFAULT 'password'", [])?;
+
+    Ok(())
+}
+
+// 7: encrypted files attached to entries, see `vodka attach`. contents are split in chunks,
+// each encrypted on its own
+fn attachments(transaction: &Transaction) -> Result<(), Error> {
+    transaction.execute_batch(
+        "CREATE TABLE attachments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entry_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            created_at INTEGER,
+            UNIQUE (entry_id, name)
+        );
+        CREATE TABLE attachment_chunks (
+            attachment_id INTEGER NOT NULL,
+            chunk INTEGER NOT NULL,
+            data BLOB NOT NULL,
+            PRIMARY KEY (attachment_id, chunk)
+        );"
+    )?;
+
+    Ok(())
+}
+
+// 8: public keys and settings of ssh key entries, see `vodka ssh`
+fn ssh_keys(transaction: &Transaction) -> Result<(), Error> {
+    transaction.execute(
+        "CREATE TABLE ssh_keys (
+            entry_id INTEGER PRIMARY KEY,
+            public_key TEXT NOT NULL,
+            confirm INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn add_column_if_missing(connection: &Connection, table: &str, column: &str, definition: &str) -> Result<(), Error> {
+    let count: i32 = connection.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?",
+        [table, column],
+        |row| row.get(0)
+    )?;
+
+    if count == 0 {
+        connection.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a directory of its own for each test, so backups can be listed
+    fn temp_cellar(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vodka-migrations-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("cellar.sqlite")
+    }
+
+    fn backups(cellar_path: &Path) -> Vec<PathBuf> {
+        fs::read_dir(cellar_path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().ends_with(".bak"))
+            .collect()
+    }
+
+    fn has_column(connection: &Connection, table: &str, column: &str) -> bool {
+        connection
+            .query_row("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?", [table, column], |row| row.get::<_, i32>(0))
+            .unwrap() == 1
+    }
+
+    fn has_table(connection: &Connection, table: &str) -> bool {
+        connection
+            .query_row("SELECT COUNT(*) FROM sqlite_master WHERE name = ?", [table], |row| row.get::<_, i32>(0))
+            .unwrap() == 1
+    }
+
+    #[test]
+    fn creates_new_cellars_without_backup() {
+        let cellar_path = temp_cellar("new");
+        let mut connection = Connection::open(&cellar_path).unwrap();
+
+        migrate(&mut connection, &cellar_path).unwrap();
+
+        assert_eq!(current_version(&connection).unwrap(), latest_version());
+        assert!(has_table(&connection, "ssh_keys"));
+        assert!(backups(&cellar_path).is_empty());
+
+        // up to date cellars are left alone
+        migrate(&mut connection, &cellar_path).unwrap();
+        assert!(backups(&cellar_path).is_empty());
+
+        fs::remove_dir_all(cellar_path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn upgrades_unversioned_cellars() {
+        let cellar_path = temp_cellar("unversioned");
+        let mut connection = Connection::open(&cellar_path).unwrap();
+        connection.execute_batch(
+            "CREATE TABLE passwords (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                login TEXT NOT NULL,
+                password BLOB NOT NULL,
+                comment TEXT
+            );
+            INSERT INTO passwords (name, login, password, comment) VALUES ('github', 'alice', x'00', 'work');"
+        ).unwrap();
+
+        migrate(&mut connection, &cellar_path).unwrap();
+
+        assert_eq!(current_version(&connection).unwrap(), latest_version());
+        for column in ["otp", "created_at", "password_changed_at", "folder", "kind"] {
+            assert!(has_column(&connection, "passwords", column), "{}", column);
+        }
+        let (kind, created_at): (String, Option<i64>) = connection
+            .query_row("SELECT kind, created_at FROM passwords WHERE name = 'github'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((kind.as_str(), created_at), ("password", None));
+
+        // existing entries are indexed for `search --text`
+        let indexed: i32 = connection
+            .query_row("SELECT rowid FROM entries_fts WHERE entries_fts MATCH 'work'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(indexed, 1);
+
+        // the backup is the cellar as it was
+        let backups = backups(&cellar_path);
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].file_name().unwrap().to_string_lossy().starts_with("cellar.sqlite.v0-"));
+        let backup = Connection::open(&backups[0]).unwrap();
+        assert_eq!(current_version(&backup).unwrap(), 0);
+        assert!(!has_column(&backup, "passwords", "kind"));
+        assert_eq!(backup.query_row("SELECT COUNT(*) FROM passwords", [], |row| row.get::<_, i32>(0)).unwrap(), 1);
+
+        fs::remove_dir_all(cellar_path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn completes_partial_initial_schemas() {
+        // as left by a vodka that had added otp secrets and the vault table, but not history or timestamps
+        let cellar_path = temp_cellar("partial");
+        let mut connection = Connection::open(&cellar_path).unwrap();
+        connection.execute_batch(
+            "CREATE TABLE passwords (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                login TEXT NOT NULL,
+                password BLOB NOT NULL,
+                comment TEXT,
+                otp BLOB
+            );
+            CREATE TABLE vault (
+                key TEXT PRIMARY KEY,
+                value BLOB NOT NULL
+            );
+            INSERT INTO vault (key, value) VALUES ('cipher', CAST('aes-256-gcm' AS BLOB));"
+        ).unwrap();
+
+        migrate(&mut connection, &cellar_path).unwrap();
+
+        assert_eq!(current_version(&connection).unwrap(), latest_version());
+        assert!(has_table(&connection, "password_history"));
+        assert!(has_column(&connection, "passwords", "last_used_at"));
+        let cipher: Vec<u8> = connection.query_row("SELECT value FROM vault WHERE key = 'cipher'", [], |row| row.get(0)).unwrap();
+        assert_eq!(cipher, b"aes-256-gcm");
+        assert_eq!(backups(&cellar_path).len(), 1);
+
+        fs::remove_dir_all(cellar_path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn refuses_newer_cellars() {
+        let cellar_path = temp_cellar("newer");
+        let mut connection = Connection::open(&cellar_path).unwrap();
+        connection.execute_batch("CREATE TABLE passwords (id INTEGER PRIMARY KEY)").unwrap();
+        connection.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+
+        let result = migrate(&mut connection, &cellar_path);
+
+        assert!(matches!(result, Err(Error::CellarTooNew(version, latest)) if version == latest_version() + 1 && latest == latest_version()));
+        assert_eq!(current_version(&connection).unwrap(), latest_version() + 1);
+        assert!(!has_table(&connection, "vault"));
+        assert!(backups(&cellar_path).is_empty());
+
+        fs::remove_dir_all(cellar_path.parent().unwrap()).unwrap();
+    }
+}
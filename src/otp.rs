@@ -0,0 +1,333 @@
+use crate::Error;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+// characters escaped in the label and issuer of an otpauth:// uri
+const URI_COMPONENT: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'%').add(b'&').add(b'+')
+    .add(b'/').add(b'<').add(b'=').add(b'>').add(b'?');
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    pub fn parse(s: &str) -> Result<Algorithm, Error> {
+        match s.to_uppercase().as_str() {
+            "SHA1" => Ok(Algorithm::Sha1),
+            "SHA256" => Ok(Algorithm::Sha256),
+            "SHA512" => Ok(Algorithm::Sha512),
+            _ => Err(Error::InvalidOtp(format!("unsupported algorithm {}", s))),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    Totp { period: u64 },
+    Hotp { counter: u64 },
+}
+
+// an OTP secret with its parameters. stored in the cellar as an encrypted otpauth:// uri
+#[derive(Clone, Debug)]
+pub struct Otp {
+    pub secret: Vec<u8>,
+    pub algorithm: Algorithm,
+    pub digits: u32,
+    pub kind: Kind,
+    pub label: String,
+    pub issuer: Option<String>,
+}
+
+impl Otp {
+    // from a base32 secret, as shown by most sites next to the qr code
+    pub fn new(secret: &str, algorithm: Algorithm, digits: u32, kind: Kind) -> Result<Otp, Error> {
+        let otp = Otp {
+            secret: decode_secret(secret)?,
+            algorithm,
+            digits,
+            kind,
+            label: String::new(),
+            issuer: None,
+        };
+        otp.validate()?;
+
+        Ok(otp)
+    }
+
+    // otpauth://totp/Issuer:alice@example.com?secret=...&issuer=Issuer&algorithm=SHA1&digits=6&period=30
+    pub fn from_uri(uri: &str) -> Result<Otp, Error> {
+        let url = Url::parse(uri.trim()).map_err(|e| Error::InvalidOtp(e.to_string()))?;
+
+        if url.scheme() != "otpauth" {
+            return Err(Error::InvalidOtp(String::from("expected an otpauth:// uri")));
+        }
+
+        let mut secret = None;
+        let mut issuer = None;
+        let mut algorithm = Algorithm::Sha1;
+        let mut digits = 6;
+        let mut period = 30;
+        let mut counter = None;
+
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "secret" => secret = Some(decode_secret(&value)?),
+                "issuer" => issuer = Some(value.to_string()),
+                "algorithm" => algorithm = Algorithm::parse(&value)?,
+                "digits" => digits = parse_number(&key, &value)? as u32,
+                "period" => period = parse_number(&key, &value)?,
+                "counter" => counter = Some(parse_number(&key, &value)?),
+                _ => {}
+            }
+        }
+
+        let kind = match url.host_str() {
+            Some("totp") => Kind::Totp { period },
+            Some("hotp") => Kind::Hotp {
+                counter: counter.ok_or(Error::InvalidOtp(String::from("hotp uri without a counter")))?
+            },
+            _ => return Err(Error::InvalidOtp(String::from("expected otpauth://totp/ or otpauth://hotp/"))),
+        };
+
+        let label = percent_decode_str(url.path().trim_start_matches('/'))
+            .decode_utf8_lossy()
+            .to_string();
+
+        let otp = Otp {
+            secret: secret.ok_or(Error::InvalidOtp(String::from("uri without a secret")))?,
+            algorithm,
+            digits,
+            kind,
+            label,
+            issuer,
+        };
+        otp.validate()?;
+
+        Ok(otp)
+    }
+
+    pub fn to_uri(&self) -> String {
+        let (kind, kind_param) = match self.kind {
+            Kind::Totp { period } => ("totp", format!("period={}", period)),
+            Kind::Hotp { counter } => ("hotp", format!("counter={}", counter)),
+        };
+
+        let mut uri = format!(
+            "otpauth://{}/{}?secret={}&algorithm={}&digits={}&{}",
+            kind,
+            utf8_percent_encode(&self.label, URI_COMPONENT),
+            BASE32_NOPAD.encode(&self.secret),
+            self.algorithm.as_str(),
+            self.digits,
+            kind_param
+        );
+
+        if let Some(issuer) = &self.issuer {
+            uri.push_str(&format!("&issuer={}", utf8_percent_encode(issuer, URI_COMPONENT)));
+        }
+
+        uri
+    }
+
+    // current code. for HOTP this is the code for the stored counter,
+    // the caller has to store the incremented counter afterwards (see `advance`)
+    pub fn generate(&self) -> String {
+        let moving_factor = match self.kind {
+            Kind::Totp { period } => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+                now / period
+            },
+            Kind::Hotp { counter } => counter,
+        };
+
+        self.generate_at(moving_factor)
+    }
+
+    // seconds until the current TOTP code expires
+    pub fn remaining_seconds(&self) -> Option<u64> {
+        match self.kind {
+            Kind::Totp { period } => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+                Some(period - now % period)
+            },
+            Kind::Hotp { .. } => None,
+        }
+    }
+
+    // moves an HOTP counter past the code that was just generated
+    pub fn advance(&mut self) {
+        if let Kind::Hotp { counter } = &mut self.kind {
+            *counter += 1;
+        }
+    }
+
+    // RFC 4226 dynamic truncation of the HMAC over the big-endian moving factor
+    pub fn generate_at(&self, moving_factor: u64) -> String {
+        let message = moving_factor.to_be_bytes();
+        let hash = match self.algorithm {
+            Algorithm::Sha1 => hmac::<Hmac<Sha1>>(&self.secret, &message),
+            Algorithm::Sha256 => hmac::<Hmac<Sha256>>(&self.secret, &message),
+            Algorithm::Sha512 => hmac::<Hmac<Sha512>>(&self.secret, &message),
+        };
+
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+
+        let code = binary as u64 % 10u64.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if !(6..=8).contains(&self.digits) {
+            return Err(Error::InvalidOtp(String::from("digits must be between 6 and 8")));
+        }
+
+        if let Kind::Totp { period: 0 } = self.kind {
+            return Err(Error::InvalidOtp(String::from("period must be positive")));
+        }
+
+        if self.secret.is_empty() {
+            return Err(Error::InvalidOtp(String::from("empty secret")));
+        }
+
+        Ok(())
+    }
+}
+
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).unwrap();
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// base32 as found in the wild: any case, with spaces or dashes, padded or not
+fn decode_secret(secret: &str) -> Result<Vec<u8>, Error> {
+    let normalized: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+        .collect::<String>()
+        .to_uppercase();
+
+    BASE32_NOPAD
+        .decode(normalized.as_bytes())
+        .map_err(|_| Error::InvalidOtp(String::from("secret is not valid base32")))
+}
+
+fn parse_number(key: &str, value: &str) -> Result<u64, Error> {
+    value
+        .parse::<u64>()
+        .map_err(|_| Error::InvalidOtp(format!("invalid {} '{}'", key, value)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn otp(secret: &[u8], algorithm: Algorithm, digits: u32, kind: Kind) -> Otp {
+        Otp::new(&BASE32_NOPAD.encode(secret), algorithm, digits, kind).unwrap()
+    }
+
+    // RFC 4226 appendix D
+    #[test]
+    fn hotp_matches_rfc_4226() {
+        let expected = ["755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871", "520489"];
+        let mut otp = otp(b"12345678901234567890", Algorithm::Sha1, 6, Kind::Hotp { counter: 0 });
+
+        for code in expected {
+            assert_eq!(otp.generate(), code);
+            otp.advance();
+        }
+        assert_eq!(otp.kind, Kind::Hotp { counter: 10 });
+    }
+
+    // RFC 6238 appendix B, 8 digits every 30 seconds with a seed as long as each hash
+    #[test]
+    fn totp_matches_rfc_6238() {
+        let sha1 = otp(b"12345678901234567890", Algorithm::Sha1, 8, Kind::Totp { period: 30 });
+        let sha256 = otp(b"12345678901234567890123456789012", Algorithm::Sha256, 8, Kind::Totp { period: 30 });
+        let sha512 = otp(
+            b"1234567890123456789012345678901234567890123456789012345678901234",
+            Algorithm::Sha512, 8, Kind::Totp { period: 30 }
+        );
+
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+
+        for (time, expected_sha1, expected_sha256, expected_sha512) in vectors {
+            assert_eq!(sha1.generate_at(time / 30), expected_sha1, "SHA1 at {}", time);
+            assert_eq!(sha256.generate_at(time / 30), expected_sha256, "SHA256 at {}", time);
+            assert_eq!(sha512.generate_at(time / 30), expected_sha512, "SHA512 at {}", time);
+        }
+    }
+
+    #[test]
+    fn fewer_digits_keep_the_last_ones() {
+        let otp = otp(b"12345678901234567890", Algorithm::Sha1, 6, Kind::Totp { period: 30 });
+        assert_eq!(otp.generate_at(59 / 30), "287082");
+        assert_eq!(otp.generate_at(1111111109 / 30), "081804");
+    }
+
+    #[test]
+    fn parses_otpauth_uris() {
+        let otp = Otp::from_uri(
+            "otpauth://totp/ACME%20Co:alice@example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60"
+        ).unwrap();
+
+        assert_eq!(otp.secret, b"12345678901234567890");
+        assert_eq!(otp.algorithm, Algorithm::Sha256);
+        assert_eq!(otp.digits, 8);
+        assert_eq!(otp.kind, Kind::Totp { period: 60 });
+        assert_eq!(otp.label, "ACME Co:alice@example.com");
+        assert_eq!(otp.issuer.as_deref(), Some("ACME Co"));
+
+        let again = Otp::from_uri(&otp.to_uri()).unwrap();
+        assert_eq!((again.secret, again.kind, again.label, again.issuer), (otp.secret, otp.kind, otp.label, otp.issuer));
+
+        let hotp = Otp::from_uri("otpauth://hotp/x?secret=gezd gnbv-gy3t qojq&counter=5").unwrap();
+        assert_eq!((hotp.algorithm, hotp.digits, hotp.kind), (Algorithm::Sha1, 6, Kind::Hotp { counter: 5 }));
+    }
+
+    #[test]
+    fn refuses_invalid_secrets() {
+        for uri in [
+            "https://totp/x?secret=GEZDGNBV",
+            "otpauth://totp/x",
+            "otpauth://totp/x?secret=1234",
+            "otpauth://totp/x?secret=GEZDGNBV&digits=9",
+            "otpauth://totp/x?secret=GEZDGNBV&period=0",
+            "otpauth://totp/x?secret=GEZDGNBV&algorithm=MD5",
+            "otpauth://hotp/x?secret=GEZDGNBV",
+        ] {
+            assert!(matches!(Otp::from_uri(uri), Err(Error::InvalidOtp(_))), "{}", uri);
+        }
+    }
+}
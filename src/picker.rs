@@ -0,0 +1,142 @@
+use crate::Entry;
+use crossterm::cursor::{MoveToColumn, MoveUp};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{execute, queue};
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use std::io::{stderr, stdin, IsTerminal, Write};
+
+// rows of candidates shown at once, the list scrolls past that
+const VISIBLE_ROWS: usize = 10;
+
+// lets the user choose one of several entries: an interactive list (arrow keys, type to filter)
+// on a terminal, a numbered prompt otherwise. everything is drawn on stderr so stdout stays clean.
+// returns the index of the chosen entry, None if cancelled
+pub fn pick(entries: &[Entry], prompt: &str) -> Option<usize> {
+    if entries.is_empty() {
+        return None;
+    }
+
+    if stdin().is_terminal() && stderr().is_terminal() {
+        match pick_interactive(entries, prompt) {
+            Ok(choice) => choice,
+            Err(_) => {
+                let _ = terminal::disable_raw_mode();
+                pick_numbered(entries, prompt)
+            }
+        }
+    } else {
+        pick_numbered(entries, prompt)
+    }
+}
+
+fn label(entry: &Entry) -> String {
+    let mut label = if entry.login.is_empty() { entry.name.clone() } else { format!("{}@{}", entry.login, entry.name) };
+
+    let comment = entry.comment.trim();
+    if !comment.is_empty() {
+        label.push_str(&format!("  ({})", comment.lines().next().unwrap_or("")));
+    }
+
+    label
+}
+
+fn pick_numbered(entries: &[Entry], prompt: &str) -> Option<usize> {
+    eprintln!("{}", prompt);
+    for (i, entry) in entries.iter().enumerate() {
+        eprintln!("  {}) {} [id {}]", i + 1, label(entry), entry.id);
+    }
+    eprint!("Select an entry [1-{}]: ", entries.len());
+
+    let mut selection = String::new();
+    stdin().read_line(&mut selection).ok()?;
+
+    match selection.trim().parse::<usize>() {
+        Ok(number) if (1..=entries.len()).contains(&number) => Some(number - 1),
+        _ => None,
+    }
+}
+
+fn pick_interactive(entries: &[Entry], prompt: &str) -> std::io::Result<Option<usize>> {
+    let labels: Vec<String> = entries.iter().map(label).collect();
+    let matcher = SkimMatcherV2::default().ignore_case();
+
+    let mut query = String::new();
+    let mut selected = 0; // position in the filtered list
+    let mut scroll = 0;
+
+    terminal::enable_raw_mode()?;
+    let mut out = stderr();
+
+    let choice = loop {
+        // candidates matching the query, best first
+        let mut filtered: Vec<(i64, usize)> = labels
+            .iter()
+            .enumerate()
+            .filter_map(|(i, label)| {
+                if query.is_empty() {
+                    Some((0, i))
+                } else {
+                    matcher.fuzzy_match(label, &query).map(|score| (score, i))
+                }
+            })
+            .collect();
+        filtered.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        selected = selected.min(filtered.len().saturating_sub(1));
+        if selected < scroll {
+            scroll = selected;
+        } else if selected >= scroll + VISIBLE_ROWS {
+            scroll = selected + 1 - VISIBLE_ROWS;
+        }
+
+        // redraw below the prompt line, then put the cursor back after the query
+        queue!(out, MoveToColumn(0), Clear(ClearType::FromCursorDown))?;
+        queue!(out, Print(format!("{} {}", prompt, query)))?;
+
+        let rows: Vec<&(i64, usize)> = filtered.iter().skip(scroll).take(VISIBLE_ROWS).collect();
+        for (row, (_, i)) in rows.iter().enumerate() {
+            queue!(out, Print("\r\n"))?;
+            if scroll + row == selected {
+                queue!(out, SetAttribute(Attribute::Reverse), Print(format!("> {}", labels[*i])), SetAttribute(Attribute::Reset))?;
+            } else {
+                queue!(out, Print(format!("  {}", labels[*i])))?;
+            }
+        }
+        if filtered.is_empty() {
+            queue!(out, Print("\r\n  (no matches)"))?;
+        }
+
+        let drawn_rows = rows.len().max(1);
+        queue!(out, MoveUp(drawn_rows as u16), MoveToColumn((prompt.chars().count() + 1 + query.chars().count()) as u16))?;
+        out.flush()?;
+
+        if let Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) = event::read()? {
+            match code {
+                KeyCode::Enter => break filtered.get(selected).map(|(_, i)| *i),
+                KeyCode::Esc => break None,
+                KeyCode::Char('c') | KeyCode::Char('d') if modifiers.contains(KeyModifiers::CONTROL) => break None,
+                KeyCode::Up => selected = selected.saturating_sub(1),
+                KeyCode::Down => selected += 1,
+                KeyCode::Char('p') if modifiers.contains(KeyModifiers::CONTROL) => selected = selected.saturating_sub(1),
+                KeyCode::Char('n') if modifiers.contains(KeyModifiers::CONTROL) => selected += 1,
+                KeyCode::Backspace => {
+                    query.pop();
+                    selected = 0;
+                },
+                KeyCode::Char(c) => {
+                    query.push(c);
+                    selected = 0;
+                },
+                _ => {},
+            }
+        }
+    };
+
+    execute!(out, MoveToColumn(0), Clear(ClearType::FromCursorDown))?;
+    terminal::disable_raw_mode()?;
+
+    Ok(choice)
+}
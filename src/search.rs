@@ -0,0 +1,108 @@
+use crate::{unix_time, Entry, Error};
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use regex::RegexBuilder;
+use std::collections::HashMap;
+
+// how `vodka search` matches the query against the non-secret text of entries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchMode {
+    Fuzzy, // the query's characters in order, like fzf/skim: "gthb" finds github
+    Substring, // case-insensitive
+    Regex, // case-insensitive
+}
+
+// entries matching the query anywhere in their fullname, name, login, comment, folder, tags,
+// urls or plain fields (`plain_fields`, by entry id). best matches first, recently used ones
+// ranking higher among similar matches
+pub fn search(entries: Vec<Entry>, query: &str, mode: SearchMode, plain_fields: &HashMap<i32, Vec<String>>) -> Result<Vec<Entry>, Error> {
+    let matcher = Matcher::new(query, mode)?;
+    let no_fields = Vec::new();
+
+    let mut results: Vec<(i64, Entry)> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let fullname = if entry.login.is_empty() { entry.name.clone() } else { format!("{}@{}", entry.login, entry.name) };
+
+            // name and login are what entries are usually looked up by
+            let primary = [&fullname, &entry.name, &entry.login];
+            let secondary = [&entry.comment, &entry.folder]
+                .into_iter()
+                .chain(&entry.tags)
+                .chain(&entry.urls)
+                .chain(plain_fields.get(&entry.id).unwrap_or(&no_fields));
+
+            let score = primary.into_iter().filter_map(|text| matcher.score(text)).map(|score| score * 2)
+                .chain(secondary.filter_map(|text| matcher.score(text)))
+                .max()?;
+
+            Some((score + recency_bonus(entry.last_used_at), entry))
+        })
+        .collect();
+
+    results.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    Ok(results.into_iter().map(|(_, entry)| entry).collect())
+}
+
+enum Matcher {
+    Fuzzy(Box<SkimMatcherV2>, String),
+    Substring(String),
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    fn new(query: &str, mode: SearchMode) -> Result<Matcher, Error> {
+        Ok(match mode {
+            SearchMode::Fuzzy => Matcher::Fuzzy(Box::new(SkimMatcherV2::default().ignore_case()), query.to_string()),
+            SearchMode::Substring => Matcher::Substring(query.to_lowercase()),
+            SearchMode::Regex => Matcher::Regex(
+                RegexBuilder::new(query)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| Error::InvalidPattern(e.to_string()))?
+            ),
+        })
+    }
+
+    // None if the text doesn't match. matches score higher the more of the text they cover,
+    // so "git" ranks git above github above digital-git-mirror
+    fn score(&self, text: &str) -> Option<i64> {
+        if text.is_empty() {
+            return None;
+        }
+
+        match self {
+            Matcher::Fuzzy(matcher, query) => {
+                // among texts matching equally well, the ones closest to the query first
+                let score = matcher.fuzzy_match(text, query)?;
+                Some(score + (50 * query.chars().count() / text.chars().count()) as i64)
+            },
+            Matcher::Substring(query) => {
+                let text = text.to_lowercase();
+                let start = text.find(query.as_str())?;
+                Some(coverage_score(start, query.len(), text.len()))
+            },
+            Matcher::Regex(regex) => {
+                let found = regex.find(text)?;
+                Some(coverage_score(found.start(), found.len(), text.len()))
+            },
+        }
+    }
+}
+
+fn coverage_score(start: usize, length: usize, text_length: usize) -> i64 {
+    let prefix_bonus = if start == 0 { 50 } else { 0 };
+    (100 * length.max(1) / text_length) as i64 + prefix_bonus
+}
+
+// up to 16 points for an entry used this week, halving every week after
+fn recency_bonus(last_used_at: Option<i64>) -> i64 {
+    match last_used_at {
+        Some(time) => {
+            let weeks = (unix_time() - time).max(0) / (7 * 86400);
+            16 >> weeks.min(5)
+        },
+        None => 0,
+    }
+}
@@ -1,17 +1,18 @@
-use crate::{config, crypto, store};
+use crate::{config, crypto, migrations, store};
 use crate::{Error, get_cellar_path, get_vodka_path};
+use crate::generator::PassphraseSpec;
 use rpassword::prompt_password;
 use rusqlite::Connection;
 use std::fs;
 use std::path::PathBuf;
 
+// what .master_key holds, to verify the master key with
+pub fn hash_master(master_key: &str) -> Result<String, Error> {
+    crypto::hash_argon2(master_key.as_bytes()).ok_or(Error::HashFailed)
+}
+
 pub fn set_master(master_key: String, overwrite: bool) -> Result<(), Error> {
-    if let Some(hashed) = crypto::hash_argon2(master_key.as_bytes())
-    {
-        store::write_to_file(".master_key", hashed, overwrite)?;
-    }
-    
-    Ok(())
+    store::write_to_file(".master_key", hash_master(&master_key)?, overwrite)
 }
 
 pub fn check_setup() -> Result<(), Error> {
@@ -35,23 +36,40 @@ pub fn check_setup() -> Result<(), Error> {
 
 pub fn setup_db() -> Result<(), Error> {
     let cellar_path = get_cellar_path();
-    let connection = Connection::open(&cellar_path).unwrap();
+    let mut connection = Connection::open(&cellar_path).unwrap();
     
-    connection.execute(
-        "CREATE TABLE IF NOT EXISTS passwords (
-            id INTEGER PRIMARY KEY AUTOINCREMENT, 
-            name TEXT NOT NULL, 
-            login TEXT NOT NULL, 
-            password BLOB NOT NULL, 
-            comment TEXT
-        )",
-        [],
-    )?;
+    migrations::migrate(&mut connection, &cellar_path)
+}
 
-    Ok(())
+// asks for a new master key twice, again if it's too weak. with a passphrase spec, a passphrase
+// is generated instead and has to be typed back once. None if the keys don't match
+pub fn prompt_new_master_key(description: &str, passphrase: Option<&PassphraseSpec>) -> Result<Option<String>, Error> {
+    let master_key = match passphrase {
+        Some(spec) => {
+            let generated = spec.generate()?;
+
+            eprintln!("Your {} is:\n\n    {}\n", description, generated.passphrase);
+            eprintln!("({:.0} bits of entropy). Write it down somewhere safe, it cannot be recovered.\n", generated.entropy);
+
+            generated.passphrase
+        },
+        None => loop {
+            let master_key = prompt_password(format!("Enter {}: ", description)).unwrap();
+            if crate::check_password_strength(&master_key, &[], true) {
+                break master_key;
+            }
+        }
+    };
+
+    if master_key != prompt_password(format!("Confirm {}: ", description)).unwrap() {
+        return Ok(None);
+    }
+
+    Ok(Some(master_key))
 }
 
-pub fn setup_vodka() -> Result<(), Error> {
+// generates the master key as a passphrase if a spec is given
+pub fn setup_vodka(passphrase: Option<PassphraseSpec>) -> Result<(), Error> {
     let vodka_dir = ".vodka";
     let mut vodka_path = PathBuf::new();
     
@@ -67,16 +85,19 @@ pub fn setup_vodka() -> Result<(), Error> {
     eprintln!("Welcome to vodka! You have no idea about the greatness that you are in for!\n");
     eprintln!("Please enter a master key, which will be used for adding and retrieving passwords.\n");
 
-    let master_key = prompt_password("Enter master key: ").unwrap();
-    if master_key != prompt_password("Confirm master key: ").unwrap() {
-        eprintln!("Error: Please enter the same master key!");
-        return Ok(());
-    }
+    let master_key = match prompt_new_master_key("master key", passphrase.as_ref())? {
+        Some(master_key) => master_key,
+        None => {
+            eprintln!("Error: Please enter the same master key!");
+            return Ok(());
+        }
+    };
 
     fs::create_dir_all(vodka_path)?;
     setup_db()?;
-    set_master(master_key, false)?;
-    config::create_default_config()?; // note: config created after previous setup
+    config::create_default_config()?; // note: config must exist before hashing the master key
+    set_master(master_key.clone(), false)?;
+    crate::open_cellar(master_key.as_bytes())?; // generates the data key and wraps it
     
     Ok(())
 }
\ No newline at end of file
@@ -1,10 +1,12 @@
 #![allow(dead_code)]
 
-use rusqlite::{params, params_from_iter};
-use crate::{Entry, Error, get_vodka_path, get_db};
+use rusqlite::{params, params_from_iter, Connection};
+use crate::{Attachment, Entry, EntryKind, Error, SshKey, Field, PasswordVersion, get_vodka_path, get_db};
+use std::collections::HashMap;
 use std::fs;
 use std::io::Read;
 use std::io::Write;
+use std::path::PathBuf;
 
 pub fn read_file(file_name: &str) -> Result<String, Error> {
     let file_path = get_vodka_path(file_name);
@@ -37,13 +39,68 @@ pub fn write_to_file(file_name: &str, content: String, overwrite: bool) -> Resul
     Ok(())
 }
 
+// writes content next to the file, to be moved over it with fs::rename once it should take
+// effect, so the file is never half written. only readable by the user. returns the path written to
+pub fn write_temp_file(file_name: &str, content: &str) -> Result<PathBuf, Error> {
+    let temp_path = get_vodka_path(&format!("{}.tmp", file_name));
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(&temp_path)?;
+    // mode only applies to new files, a temp file may be left over from a failed change
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+
+    Ok(temp_path)
+}
+
+// columns read into an Entry, in the order entry_from_row expects
+const ENTRY_COLUMNS: &str = "id, name, login, password, comment, otp, created_at, updated_at, password_changed_at, last_used_at, folder,
+    (SELECT group_concat(tags.name, ',') FROM entry_tags JOIN tags ON tags.id = entry_tags.tag_id WHERE entry_tags.entry_id = passwords.id),
+    (SELECT group_concat(url, char(10)) FROM (SELECT url FROM entry_urls WHERE entry_id = passwords.id ORDER BY id)), kind";
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<Entry> {
+    Ok(Entry {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        login: row.get(2)?,
+        password: row.get(3)?,
+        comment: row.get(4)?,
+        otp: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+        password_changed_at: row.get(8)?,
+        last_used_at: row.get(9)?,
+        folder: row.get(10)?,
+        tags: {
+            let tags: Option<String> = row.get(11)?;
+            let mut tags: Vec<String> = tags.unwrap_or_default().split(',').filter(|tag| !tag.is_empty()).map(String::from).collect();
+            tags.sort();
+            tags
+        },
+        urls: {
+            let urls: Option<String> = row.get(12)?;
+            urls.unwrap_or_default().lines().map(String::from).collect()
+        },
+        kind: {
+            let kind: String = row.get(13)?;
+            EntryKind::parse(&kind).unwrap_or(EntryKind::Password)
+        },
+    })
+}
+
 pub fn get_next_id() -> i32 {
     let connection = get_db();
 
     match connection.query_row(
         "SELECT seq FROM sqlite_sequence WHERE name = 'passwords'",
         [],
-        |row| Ok(row.get::<usize, i32>(0)?)
+        |row| row.get::<usize, i32>(0)
     ) {
         Ok(id) => id + 1,
         Err(e) => {
@@ -58,21 +115,379 @@ pub fn get_next_id() -> i32 {
     }
 }
 
-pub fn add_entry(name: String, login: String, password: &[u8], comment: String) -> Result<(), Error> {
+// the entry and its custom fields together. returns the id of the entry
+pub fn add_entry(entry: &Entry, fields: &[Field]) -> Result<i32, Error> {
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    let id = insert_entry(&transaction, entry)?;
+    for field in fields {
+        insert_field(&transaction, id, field)?;
+    }
+
+    transaction.commit()?;
+
+    Ok(id)
+}
+
+// returns the id of the new entry
+fn insert_entry(connection: &Connection, entry: &Entry) -> Result<i32, Error> {
+    connection.execute(
+        "INSERT INTO passwords (name, login, password, comment, otp, created_at, updated_at, password_changed_at, last_used_at, folder, kind)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            entry.name, entry.login, entry.password, entry.comment, entry.otp,
+            entry.created_at, entry.updated_at, entry.password_changed_at, entry.last_used_at, entry.folder,
+            entry.kind.as_str()
+        ]
+    )?;
+    let id = connection.last_insert_rowid() as i32;
+    set_tags(connection, id, &entry.tags)?;
+    set_urls(connection, id, &entry.urls)?;
+
+    Ok(id)
+}
+
+// replaces the tags of an entry. tags left without entries are removed
+fn set_tags(connection: &Connection, entry_id: i32, tags: &[String]) -> Result<(), Error> {
+    connection.execute("DELETE FROM entry_tags WHERE entry_id = ?", [entry_id])?;
+
+    for tag in tags {
+        connection.execute("INSERT OR IGNORE INTO tags (name) VALUES (?)", [tag])?;
+        connection.execute(
+            "INSERT INTO entry_tags (entry_id, tag_id) SELECT ?, id FROM tags WHERE name = ?",
+            params![entry_id, tag]
+        )?;
+    }
+
+    connection.execute("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM entry_tags)", [])?;
+
+    Ok(())
+}
+
+fn set_urls(connection: &Connection, entry_id: i32, urls: &[String]) -> Result<(), Error> {
+    connection.execute("DELETE FROM entry_urls WHERE entry_id = ?", [entry_id])?;
+
+    for url in urls {
+        connection.execute("INSERT INTO entry_urls (entry_id, url) VALUES (?, ?)", params![entry_id, url])?;
+    }
+
+    Ok(())
+}
+
+// every tag with the number of entries that have it, by name
+pub fn get_tags() -> Result<Vec<(String, usize)>, Error> {
+    let connection = get_db();
+    let mut stmt = connection.prepare(
+        "SELECT tags.name, COUNT(*) FROM tags JOIN entry_tags ON entry_tags.tag_id = tags.id GROUP BY tags.id ORDER BY tags.name"
+    )?;
+
+    let tags = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(tags)
+}
+
+// a password that was changed goes to password_history, in the same transaction
+pub fn update_entry(entry: &Entry) -> Result<(), Error> {
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    let (old_password, old_changed_at): (Vec<u8>, Option<i64>) = transaction.query_row(
+        "SELECT password, password_changed_at FROM passwords WHERE id = ?",
+        [entry.id],
+        |row| Ok((row.get(0)?, row.get(1)?))
+    )?;
+
+    if old_password != entry.password {
+        transaction.execute(
+            "INSERT INTO password_history (entry_id, password, set_at, replaced_at) VALUES (?, ?, ?, ?)",
+            params![entry.id, old_password, old_changed_at, crate::unix_time()]
+        )?;
+    }
+
+    transaction.execute(
+        "UPDATE passwords SET name = ?, login = ?, password = ?, comment = ?, otp = ?, updated_at = ?, password_changed_at = ?, folder = ? WHERE id = ?",
+        params![entry.name, entry.login, entry.password, entry.comment, entry.otp, crate::unix_time(), entry.password_changed_at, entry.folder, entry.id]
+    )?;
+    set_tags(&transaction, entry.id, &entry.tags)?;
+    set_urls(&transaction, entry.id, &entry.urls)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+// records that the entry's password was just used (copied)
+pub fn touch_entry(id: i32) -> Result<(), Error> {
+    let connection = get_db();
+
+    connection.execute(
+        "UPDATE passwords SET last_used_at = ? WHERE id = ?",
+        params![crate::unix_time(), id]
+    )?;
+
+    Ok(())
+}
+
+// previous passwords of an entry, oldest first. version numbers start at 1
+pub fn get_password_history(entry_id: i32) -> Result<Vec<PasswordVersion>, Error> {
+    let connection = get_db();
+    let mut stmt = connection.prepare(
+        "SELECT password, set_at, replaced_at FROM password_history WHERE entry_id = ? ORDER BY id"
+    )?;
+
+    let rows: Vec<(Vec<u8>, Option<i64>, i64)> = stmt
+        .query_map([entry_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(rows
+        .into_iter()
+        .enumerate()
+        .map(|(i, (password, set_at, replaced_at))| PasswordVersion {
+            version: i + 1,
+            password,
+            set_at,
+            replaced_at: Some(replaced_at),
+        })
+        .collect())
+}
+
+// custom fields of an entry, by name
+pub fn get_fields(entry_id: i32) -> Result<Vec<Field>, Error> {
+    let connection = get_db();
+    let mut stmt = connection.prepare(
+        "SELECT name, value, secret FROM fields WHERE entry_id = ? ORDER BY name"
+    )?;
+
+    let fields = stmt
+        .query_map([entry_id], |row| Ok(Field {
+            name: row.get(0)?,
+            value: row.get(1)?,
+            secret: row.get(2)?,
+        }))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(fields)
+}
+
+// values of every non-secret field, by entry id. searched along with the entries
+pub fn get_plain_field_values() -> Result<HashMap<i32, Vec<String>>, Error> {
     let connection = get_db();
+    let mut stmt = connection.prepare("SELECT entry_id, value FROM fields WHERE secret = 0")?;
+
+    let mut values: HashMap<i32, Vec<String>> = HashMap::new();
+    for row in stmt.query_map([], |row| Ok((row.get::<usize, i32>(0)?, row.get::<usize, Vec<u8>>(1)?)))? {
+        let (entry_id, value) = row?;
+        values.entry(entry_id).or_default().push(String::from_utf8_lossy(&value).to_string());
+    }
+
+    Ok(values)
+}
+
+// adds the field, or replaces the one with the same name. counts as an update of the entry
+pub fn set_field(entry_id: i32, field: &Field) -> Result<(), Error> {
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    insert_field(&transaction, entry_id, field)?;
+    transaction.execute(
+        "UPDATE passwords SET updated_at = ? WHERE id = ?",
+        params![crate::unix_time(), entry_id]
+    )?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
 
+fn insert_field(connection: &Connection, entry_id: i32, field: &Field) -> Result<(), Error> {
     connection.execute(
-        "INSERT INTO passwords (name, login, password, comment) VALUES (?, ?, ?, ?)",
-        params![name, login, password, comment]
+        "INSERT INTO fields (entry_id, name, value, secret) VALUES (?, ?, ?, ?)
+        ON CONFLICT (entry_id, name) DO UPDATE SET value = excluded.value, secret = excluded.secret",
+        params![entry_id, field.name, field.value, field.secret]
     )?;
 
     Ok(())
 }
 
+// returns whether the entry had such a field
+pub fn remove_field(entry_id: i32, name: &str) -> Result<bool, Error> {
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    let removed = transaction.execute(
+        "DELETE FROM fields WHERE entry_id = ? AND name = ?",
+        params![entry_id, name]
+    )? > 0;
+    if removed {
+        transaction.execute(
+            "UPDATE passwords SET updated_at = ? WHERE id = ?",
+            params![crate::unix_time(), entry_id]
+        )?;
+    }
+
+    transaction.commit()?;
+
+    Ok(removed)
+}
+
+// attachments of an entry, by name
+pub fn get_attachments(entry_id: i32) -> Result<Vec<Attachment>, Error> {
+    let connection = get_db();
+    let mut stmt = connection.prepare("SELECT id, entry_id, name, size, created_at FROM attachments WHERE entry_id = ? ORDER BY name")?;
+
+    let attachments = stmt
+        .query_map([entry_id], |row| Ok(Attachment {
+            id: row.get(0)?,
+            entry_id: row.get(1)?,
+            name: row.get(2)?,
+            size: row.get(3)?,
+            created_at: row.get(4)?,
+        }))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(attachments)
+}
+
+// encrypted chunks of an attachment, in order
+pub fn get_attachment_chunks(attachment_id: i32) -> Result<Vec<Vec<u8>>, Error> {
+    let connection = get_db();
+    let mut stmt = connection.prepare("SELECT data FROM attachment_chunks WHERE attachment_id = ? ORDER BY chunk")?;
+
+    let chunks = stmt
+        .query_map([attachment_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(chunks)
+}
+
+// stores already encrypted chunks, all or none of them
+pub fn add_attachment(entry_id: i32, name: &str, size: u64, chunks: &[Vec<u8>]) -> Result<(), Error> {
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    transaction.execute(
+        "INSERT INTO attachments (entry_id, name, size, created_at) VALUES (?, ?, ?, ?)",
+        params![entry_id, name, size, crate::unix_time()]
+    )?;
+    let attachment_id = transaction.last_insert_rowid();
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        transaction.execute(
+            "INSERT INTO attachment_chunks (attachment_id, chunk, data) VALUES (?, ?, ?)",
+            params![attachment_id, index, chunk]
+        )?;
+    }
+    transaction.execute(
+        "UPDATE passwords SET updated_at = ? WHERE id = ?",
+        params![crate::unix_time(), entry_id]
+    )?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+// false if the entry has no such attachment
+pub fn remove_attachment(entry_id: i32, name: &str) -> Result<bool, Error> {
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    transaction.execute(
+        "DELETE FROM attachment_chunks WHERE attachment_id IN (SELECT id FROM attachments WHERE entry_id = ? AND name = ?)",
+        params![entry_id, name]
+    )?;
+    let removed = transaction.execute(
+        "DELETE FROM attachments WHERE entry_id = ? AND name = ?",
+        params![entry_id, name]
+    )? > 0;
+    if removed {
+        transaction.execute(
+            "UPDATE passwords SET updated_at = ? WHERE id = ?",
+            params![crate::unix_time(), entry_id]
+        )?;
+    }
+
+    transaction.commit()?;
+
+    Ok(removed)
+}
+
+// the entry, its public key and its custom fields together. returns the id of the entry
+pub fn add_ssh_key(entry: &Entry, public_key: &str, confirm: bool, fields: &[Field]) -> Result<i32, Error> {
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    let id = insert_entry(&transaction, entry)?;
+    transaction.execute(
+        "INSERT INTO ssh_keys (entry_id, public_key, confirm) VALUES (?, ?, ?)",
+        params![id, public_key, confirm]
+    )?;
+    for field in fields {
+        insert_field(&transaction, id, field)?;
+    }
+
+    transaction.commit()?;
+
+    Ok(id)
+}
+
+const SSH_KEY_COLUMNS: &str = "ssh_keys.entry_id, passwords.name, ssh_keys.public_key, ssh_keys.confirm
+    FROM ssh_keys JOIN passwords ON passwords.id = ssh_keys.entry_id";
+
+fn ssh_key_from_row(row: &rusqlite::Row) -> rusqlite::Result<SshKey> {
+    Ok(SshKey {
+        entry_id: row.get(0)?,
+        name: row.get(1)?,
+        public_key: row.get(2)?,
+        confirm: row.get(3)?,
+    })
+}
+
+// every ssh key, oldest first
+pub fn get_ssh_keys() -> Result<Vec<SshKey>, Error> {
+    let connection = get_db();
+    let mut stmt = connection.prepare(&format!("SELECT {} ORDER BY ssh_keys.entry_id", SSH_KEY_COLUMNS))?;
+
+    let keys = stmt
+        .query_map([], ssh_key_from_row)?
+        .collect::<Result<_, _>>()?;
+
+    Ok(keys)
+}
+
+pub fn get_ssh_key(entry_id: i32) -> Result<Option<SshKey>, Error> {
+    let connection = get_db();
+
+    match connection.query_row(&format!("SELECT {} WHERE ssh_keys.entry_id = ?", SSH_KEY_COLUMNS), [entry_id], ssh_key_from_row) {
+        Ok(key) => Ok(Some(key)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn set_ssh_key_confirm(entry_id: i32, confirm: bool) -> Result<(), Error> {
+    let connection = get_db();
+
+    connection.execute(
+        "UPDATE ssh_keys SET confirm = ? WHERE entry_id = ?",
+        params![confirm, entry_id]
+    )?;
+
+    Ok(())
+}
+
+pub fn count_attachments() -> Result<usize, Error> {
+    let connection = get_db();
+    Ok(connection.query_row("SELECT COUNT(*) FROM attachments", [], |row| row.get(0))?)
+}
+
 // if a parameter is an empty string, will search w/o the parameter
 pub fn search_entries(name: String, login: String) -> Vec<Entry> {
     let connection = get_db();
-    let mut query_command = String::from("SELECT id, name, login, password, comment FROM passwords");
+    let mut query_command = format!("SELECT {} FROM passwords", ENTRY_COLUMNS);
     let mut query_params = Vec::new();
 
     if !name.is_empty() {
@@ -94,37 +509,48 @@ pub fn search_entries(name: String, login: String) -> Vec<Entry> {
         .unwrap();
     
     let query_match = stmt
-        .query_map(params_from_iter(query_params), |row| {
-            Ok(Entry {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                login: row.get(2)?,
-                password: row.get(3)?,
-                comment: row.get(4)?,
-            })
-        });
+        .query_map(params_from_iter(query_params), entry_from_row);
 
     let entries: Result<Vec<Entry>, rusqlite::Error> = query_match.unwrap().collect();
 
     entries.unwrap()
 }
 
+// entries matching an FTS5 query (`github`, `git*`, `"work laptop"`, `aws NOT prod`,
+// `tags:finance`...) over their name, login, comment, tags and urls, best matches first.
+// matches in the name or login weigh the most. kept apart from search_entries, which
+// get_entry relies on for exact name/login lookups: a query here can be invalid, so it
+// returns an error instead of nothing, and results are ranked rather than ordered by id.
+// the cellar always has the index, rusqlite's bundled sqlite is built with FTS5
+pub fn search_entries_text(query: &str) -> Result<Vec<Entry>, Error> {
+    let connection = get_db();
+
+    let mut stmt = connection.prepare(&format!(
+        "SELECT {} FROM passwords JOIN (
+            SELECT rowid AS entry_id, bm25(entries_fts, 10.0, 10.0, 1.0, 5.0, 2.0) AS score
+            FROM entries_fts WHERE entries_fts MATCH ?
+        ) AS hits ON hits.entry_id = passwords.id
+        ORDER BY hits.score",
+        ENTRY_COLUMNS
+    ))?;
+
+    let entries: Result<Vec<Entry>, rusqlite::Error> = stmt.query_map([query], entry_from_row)?.collect();
+
+    // syntax errors in the query only surface once it runs
+    entries.map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(_, Some(message)) => Error::InvalidQuery(message),
+        e => e.into(),
+    })
+}
+
 // uses search_entries. change, if the behaviour of search_entries ever changes (empty string parameter behaviour)
 pub fn get_entry_by_id(id: i32) -> Option<Entry> {
     let connection = get_db();
 
     let query_result = connection.query_row(
-        "SELECT id, name, login, password, comment FROM passwords WHERE id = ?",
+        &format!("SELECT {} FROM passwords WHERE id = ?", ENTRY_COLUMNS),
         [id],
-        |row| {
-            Ok(Entry {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                login: row.get(2)?,
-                password: row.get(3)?,
-                comment: row.get(4)?,
-            })
-        }
+        entry_from_row
     );
 
     if let Err(e) = query_result {
@@ -140,13 +566,46 @@ pub fn get_entry_by_id(id: i32) -> Option<Entry> {
     }
 }
 
+// the entry and everything attached to it, all or nothing: rows left behind by a failure
+// could never be reached again
 pub fn delete_entry(id: i32) -> Result<(), Error> {
-    let connection = get_db();
-    
+    let mut connection = get_db();
+    let transaction = connection.transaction()?;
+
+    delete_entry_rows(&transaction, id)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+fn delete_entry_rows(connection: &Connection, id: i32) -> Result<(), Error> {
     connection.execute(
         "DELETE FROM passwords WHERE id = ?",
         [id]
     )?;
+    connection.execute(
+        "DELETE FROM password_history WHERE entry_id = ?",
+        [id]
+    )?;
+    connection.execute(
+        "DELETE FROM fields WHERE entry_id = ?",
+        [id]
+    )?;
+    connection.execute(
+        "DELETE FROM attachment_chunks WHERE attachment_id IN (SELECT id FROM attachments WHERE entry_id = ?)",
+        [id]
+    )?;
+    connection.execute(
+        "DELETE FROM attachments WHERE entry_id = ?",
+        [id]
+    )?;
+    connection.execute(
+        "DELETE FROM ssh_keys WHERE entry_id = ?",
+        [id]
+    )?;
+    set_tags(connection, id, &[])?;
+    set_urls(connection, id, &[])?;
 
     Ok(())
 }
@@ -155,32 +614,123 @@ pub fn get_all_rows() -> Vec<Entry> {
     let connection = get_db();
 
     let mut stmt = connection
-        .prepare("SELECT id, name, login, password, comment FROM passwords")
+        .prepare(&format!("SELECT {} FROM passwords", ENTRY_COLUMNS))
         .unwrap();
     
     let query_match = stmt
-        .query_map([], |row| {
-            Ok(Entry {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                login: row.get(2)?,
-                password: row.get(3)?,
-                comment: row.get(4)?,
-            })
-        });
+        .query_map([], entry_from_row);
 
     let entries: Result<Vec<Entry>, rusqlite::Error> = query_match.unwrap().collect();
 
     entries.unwrap()
 }
 
+// replaces every stored password blob with update(blob).
+// meant to run inside a transaction so a failure leaves every row untouched
+pub fn update_passwords<F>(connection: &Connection, update: F) -> Result<(), Error>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>, Error>
+{
+    let mut stmt = connection.prepare("SELECT id, password FROM passwords")?;
+    let rows: Vec<(i32, Vec<u8>)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    for (id, password) in rows {
+        connection.execute(
+            "UPDATE passwords SET password = ? WHERE id = ?",
+            params![update(&password)?, id]
+        )?;
+    }
+
+    Ok(())
+}
+
+// key/value metadata about the cellar itself (cipher, ...)
+pub fn get_vault_value(connection: &Connection, key: &str) -> Option<Vec<u8>> {
+    match connection.query_row(
+        "SELECT value FROM vault WHERE key = ?",
+        [key],
+        |row| row.get::<usize, Vec<u8>>(0)
+    ) {
+        Ok(value) => Some(value),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => {
+            eprintln!("Error (get_vault_value): {:?}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn set_vault_value(connection: &Connection, key: &str, value: &[u8]) -> Result<(), Error> {
+    connection.execute(
+        "INSERT OR REPLACE INTO vault (key, value) VALUES (?, ?)",
+        params![key, value]
+    )?;
+
+    Ok(())
+}
+
 pub fn erase_all() -> Result<(), Error> {
     let connection = get_db();
 
-    // resets sqlite_sequence as well?
-    connection.execute("DROP TABLE IF EXISTS passwords", [])?;
-
-    crate::setup::setup_db()?;
+    // the tables are emptied rather than dropped, as migrations won't create them again
+    connection.execute("DELETE FROM passwords", [])?;
+    connection.execute("DELETE FROM password_history", [])?;
+    connection.execute("DELETE FROM fields", [])?;
+    connection.execute("DELETE FROM entry_tags", [])?;
+    connection.execute("DELETE FROM tags", [])?;
+    connection.execute("DELETE FROM entry_urls", [])?;
+    connection.execute("DELETE FROM attachment_chunks", [])?;
+    connection.execute("DELETE FROM attachments", [])?;
+    connection.execute("DELETE FROM ssh_keys", [])?;
+    connection.execute("DELETE FROM sqlite_sequence WHERE name IN ('passwords', 'password_history', 'fields', 'tags', 'entry_urls', 'attachments')", [])?;
 
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use std::path::Path;
+
+    fn count(connection: &Connection, sql: &str) -> i32 {
+        connection.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn deleting_an_entry_deletes_its_attachments() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut connection, Path::new(":memory:")).unwrap();
+
+        for (id, name) in [(1, "vpn"), (2, "bank")] {
+            connection.execute(
+                "INSERT INTO passwords (id, name, login, password) VALUES (?, ?, 'alice', x'00')",
+                params![id, name]
+            ).unwrap();
+            for (attachment_id, file) in [(id * 10, "config.ovpn"), (id * 10 + 1, "recovery.pdf")] {
+                connection.execute(
+                    "INSERT INTO attachments (id, entry_id, name, size) VALUES (?, ?, ?, 2)",
+                    params![attachment_id, id, file]
+                ).unwrap();
+                for chunk in 0..2 {
+                    connection.execute(
+                        "INSERT INTO attachment_chunks (attachment_id, chunk, data) VALUES (?, ?, x'00')",
+                        params![attachment_id, chunk]
+                    ).unwrap();
+                }
+            }
+        }
+
+        let transaction = connection.transaction().unwrap();
+        delete_entry_rows(&transaction, 1).unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM attachments WHERE entry_id = 1"), 0);
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM attachment_chunks WHERE attachment_id IN (10, 11)"), 0);
+
+        // other entries keep theirs
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM attachments WHERE entry_id = 2"), 2);
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM attachment_chunks WHERE attachment_id IN (20, 21)"), 4);
+    }
+}
@@ -0,0 +1,958 @@
+use crate::generator::DEFAULT_WORDLIST;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// password strength estimation in the style of zxcvbn: the password is split into the
+// sequence of patterns (dictionary words, keyboard walks, repeats, sequences, dates and
+// brute-forced gaps) that is cheapest to guess, and the number of guesses is derived from it
+
+// most common passwords, most common first
+const COMMON_PASSWORDS: &str = include_str!("../wordlists/common-passwords.txt");
+
+// offline attack against a slow hash (argon2, bcrypt, ...)
+const GUESSES_PER_SECOND: f64 = 1e4;
+
+// only the start of very long passwords is analysed, the rest can only add guesses
+const MAX_LENGTH: usize = 64;
+
+const MIN_GUESSES_BEFORE_GROWING_SEQUENCE: f64 = 1e4;
+const MIN_SUBMATCH_GUESSES_SINGLE_CHAR: f64 = 10.0;
+const MIN_SUBMATCH_GUESSES_MULTI_CHAR: f64 = 50.0;
+const BRUTEFORCE_CARDINALITY: f64 = 10.0;
+
+const QWERTY: [(&str, &str, usize); 4] = [
+    ("`1234567890-=", "~!@#$%^&*()_+", 0),
+    ("qwertyuiop[]\\", "QWERTYUIOP{}|", 1),
+    ("asdfghjkl;'", "ASDFGHJKL:\"", 1),
+    ("zxcvbnm,./", "ZXCVBNM<>?", 1),
+];
+
+const KEYPAD: [(&str, usize); 5] = [
+    ("/*-", 1),
+    ("789+", 0),
+    ("456", 0),
+    ("123", 0),
+    ("0.", 1),
+];
+
+// characters that are commonly substituted for letters
+const L33T_TABLE: [(char, &str); 20] = [
+    ('4', "a"), ('@', "a"), ('8', "b"), ('(', "c"), ('{', "c"), ('[', "c"), ('<', "c"),
+    ('3', "e"), ('6', "g"), ('9', "g"), ('1', "il"), ('!', "i"), ('|', "il"), ('0', "o"),
+    ('$', "s"), ('5', "s"), ('7', "lt"), ('+', "t"), ('%', "x"), ('2', "z"),
+];
+
+#[derive(Clone, Debug)]
+enum Pattern {
+    Dictionary {
+        rank: usize,
+        common_password: bool,
+        reversed: bool,
+        l33t: bool,
+    },
+    Spatial { turns: usize },
+    Repeat { base_length: usize },
+    Sequence,
+    Date { year_only: bool },
+    Bruteforce,
+}
+
+#[derive(Clone, Debug)]
+struct Match {
+    start: usize,
+    end: usize, // exclusive
+    guesses: f64,
+    pattern: Pattern,
+}
+
+pub struct Estimate {
+    pub guesses: f64,
+    pub score: u8, // 0 (too guessable) to 4 (very unguessable)
+    pub crack_time: f64, // seconds
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+impl Estimate {
+    pub fn describe_score(&self) -> &'static str {
+        match self.score {
+            0 => "very weak",
+            1 => "weak",
+            2 => "fair",
+            3 => "good",
+            _ => "strong",
+        }
+    }
+
+    pub fn describe_crack_time(&self) -> String {
+        let seconds = self.crack_time;
+        let units = [
+            (60.0 * 60.0 * 24.0 * 365.0 * 100.0, ""),
+            (60.0 * 60.0 * 24.0 * 365.0, "year"),
+            (60.0 * 60.0 * 24.0 * 31.0, "month"),
+            (60.0 * 60.0 * 24.0, "day"),
+            (60.0 * 60.0, "hour"),
+            (60.0, "minute"),
+            (1.0, "second"),
+        ];
+
+        if seconds < 1.0 {
+            return String::from("less than a second");
+        }
+        if seconds >= units[0].0 {
+            return String::from("centuries");
+        }
+
+        for (length, unit) in &units[1..] {
+            if seconds >= *length {
+                let count = (seconds / length).round() as u64;
+                return format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" });
+            }
+        }
+
+        unreachable!()
+    }
+}
+
+// keys next to each key, in a fixed order of directions so turns can be detected
+struct Keyboard {
+    neighbours: HashMap<char, Vec<Option<char>>>,
+    starting_positions: f64,
+    average_degree: f64,
+}
+
+struct Estimator {
+    dictionaries: Vec<(HashMap<String, usize>, bool)>, // word -> rank, is a list of common passwords
+    max_word_length: usize,
+    keyboards: Vec<Keyboard>,
+    current_year: i64,
+}
+
+// user_inputs are words the password shouldn't be based on, like the name of the entry
+pub fn estimate(password: &str, user_inputs: &[&str]) -> Estimate {
+    let estimator = Estimator::new(user_inputs);
+    let chars: Vec<char> = password.chars().take(MAX_LENGTH).collect();
+    let (guesses, sequence) = estimator.most_guessable(&chars);
+
+    let score = if guesses < 1e3 + 5.0 {
+        0
+    } else if guesses < 1e6 + 5.0 {
+        1
+    } else if guesses < 1e8 + 5.0 {
+        2
+    } else if guesses < 1e10 + 5.0 {
+        3
+    } else {
+        4
+    };
+
+    let (warning, suggestions) = feedback(&chars, score, &sequence);
+
+    Estimate {
+        guesses,
+        score,
+        crack_time: guesses / GUESSES_PER_SECOND,
+        warning,
+        suggestions,
+    }
+}
+
+impl Estimator {
+    fn new(user_inputs: &[&str]) -> Estimator {
+        let ranked = |words: Vec<String>| -> HashMap<String, usize> {
+            let mut ranks = HashMap::new();
+            for (i, word) in words.into_iter().enumerate() {
+                ranks.entry(word).or_insert(i + 1);
+            }
+            ranks
+        };
+
+        let common_passwords = ranked(COMMON_PASSWORDS.lines().map(|word| word.to_string()).collect());
+        let user_words = ranked(
+            user_inputs
+                .iter()
+                .flat_map(|input| {
+                    let input = input.to_lowercase();
+                    let mut words: Vec<String> = input
+                        .split(|c: char| !c.is_alphanumeric())
+                        .map(|word| word.to_string())
+                        .collect();
+                    words.insert(0, input);
+                    words
+                })
+                .filter(|word| word.chars().count() >= 3)
+                .collect()
+        );
+        // the wordlist isn't ordered by frequency, every word gets the same rank
+        // lines are "11111\tabacus", the word is the last column
+        let english: HashMap<String, usize> = DEFAULT_WORDLIST
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .map(|word| (word.to_string(), 7776))
+            .collect();
+
+        let dictionaries = vec![(common_passwords, true), (user_words, false), (english, false)];
+        let max_word_length = dictionaries
+            .iter()
+            .flat_map(|(words, _)| words.keys().map(|word| word.chars().count()))
+            .max()
+            .unwrap_or(0);
+
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        Estimator {
+            dictionaries,
+            max_word_length,
+            keyboards: vec![qwerty(), keypad()],
+            current_year: 1970 + (seconds as f64 / (60.0 * 60.0 * 24.0 * 365.2425)) as i64,
+        }
+    }
+
+    // zxcvbn's search for the minimum of l! * (product of guesses) + D^(l - 1)
+    // over all sequences of l non-overlapping matches covering the password
+    fn most_guessable(&self, chars: &[char]) -> (f64, Vec<Match>) {
+        let n = chars.len();
+        if n == 0 {
+            return (1.0, Vec::new());
+        }
+
+        let mut by_end: Vec<Vec<Match>> = vec![Vec::new(); n + 1];
+        for m in self.matches(chars) {
+            by_end[m.end].push(m);
+        }
+
+        // best[k][l]: smallest product of guesses of l matches covering chars[..k], with the last match
+        let mut best: Vec<HashMap<usize, (f64, Match)>> = vec![HashMap::new(); n + 1];
+
+        for k in 1..=n {
+            let bruteforce = (0..k).map(|start| bruteforce_match(start, k));
+
+            for m in by_end[k].iter().cloned().chain(bruteforce) {
+                let candidates: Vec<(usize, f64)> = if m.start == 0 {
+                    vec![(1, m.guesses)]
+                } else {
+                    best[m.start].iter().map(|(l, (pi, _))| (l + 1, pi * m.guesses)).collect()
+                };
+
+                for (l, pi) in candidates {
+                    if best[k].get(&l).is_none_or(|(current, _)| pi < *current) {
+                        best[k].insert(l, (pi, m.clone()));
+                    }
+                }
+            }
+        }
+
+        let (&length, guesses) = best[n]
+            .iter()
+            .map(|(l, (pi, _))| (l, factorial(*l) * pi + MIN_GUESSES_BEFORE_GROWING_SEQUENCE.powi(*l as i32 - 1)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+
+        let mut sequence = Vec::with_capacity(length);
+        let mut k = n;
+        for l in (1..=length).rev() {
+            let m = best[k][&l].1.clone();
+            k = m.start;
+            sequence.push(m);
+        }
+        sequence.reverse();
+
+        (guesses, sequence)
+    }
+
+    fn matches(&self, chars: &[char]) -> Vec<Match> {
+        let mut matches = Vec::new();
+
+        matches.extend(self.dictionary_matches(chars));
+        matches.extend(self.reverse_dictionary_matches(chars));
+        matches.extend(self.l33t_matches(chars));
+        matches.extend(self.spatial_matches(chars));
+        matches.extend(self.repeat_matches(chars));
+        matches.extend(sequence_matches(chars));
+        matches.extend(self.date_matches(chars));
+
+        // a match covering the whole password isn't a submatch
+        for m in matches.iter_mut().filter(|m| m.end - m.start < chars.len()) {
+            let min = if m.end - m.start == 1 { MIN_SUBMATCH_GUESSES_SINGLE_CHAR } else { MIN_SUBMATCH_GUESSES_MULTI_CHAR };
+            m.guesses = m.guesses.max(min);
+        }
+
+        matches
+    }
+
+    fn dictionary_matches(&self, chars: &[char]) -> Vec<Match> {
+        let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+        if lower.len() != chars.len() {
+            return Vec::new(); // lowercasing changed the length, indices wouldn't line up
+        }
+
+        let mut matches = Vec::new();
+
+        for start in 0..chars.len() {
+            for end in start + 3..=chars.len().min(start + self.max_word_length) {
+                let word: String = lower[start..end].iter().collect();
+
+                for (words, common_password) in &self.dictionaries {
+                    if let Some(&rank) = words.get(&word) {
+                        matches.push(Match {
+                            start,
+                            end,
+                            guesses: rank as f64 * uppercase_variations(&chars[start..end]),
+                            pattern: Pattern::Dictionary { rank, common_password: *common_password, reversed: false, l33t: false },
+                        });
+                    }
+                }
+            }
+        }
+
+        matches
+    }
+
+    fn reverse_dictionary_matches(&self, chars: &[char]) -> Vec<Match> {
+        let reversed: Vec<char> = chars.iter().rev().copied().collect();
+
+        self.dictionary_matches(&reversed)
+            .into_iter()
+            .map(|mut m| {
+                (m.start, m.end) = (chars.len() - m.end, chars.len() - m.start);
+                m.guesses *= 2.0;
+                if let Pattern::Dictionary { reversed, .. } = &mut m.pattern {
+                    *reversed = true;
+                }
+                m
+            })
+            .collect()
+    }
+
+    fn l33t_matches(&self, chars: &[char]) -> Vec<Match> {
+        let mut matches = Vec::new();
+
+        for substitutions in l33t_substitutions(chars) {
+            let unl33ted: Vec<char> = chars
+                .iter()
+                .map(|c| *substitutions.get(c).unwrap_or(c))
+                .collect();
+
+            for mut m in self.dictionary_matches(&unl33ted) {
+                let token = &chars[m.start..m.end];
+                if !token.iter().any(|c| substitutions.contains_key(c)) {
+                    continue; // an ordinary dictionary match
+                }
+
+                m.guesses *= l33t_variations(token, &substitutions);
+                if let Pattern::Dictionary { l33t, .. } = &mut m.pattern {
+                    *l33t = true;
+                }
+                matches.push(m);
+            }
+        }
+
+        matches
+    }
+
+    fn spatial_matches(&self, chars: &[char]) -> Vec<Match> {
+        let mut matches = Vec::new();
+
+        for keyboard in &self.keyboards {
+            let mut start = 0;
+
+            while start + 1 < chars.len() {
+                let mut end = start + 1;
+                let mut turns = 0;
+                let mut last_direction = None;
+                let mut shifted = usize::from(is_shifted(chars[start]));
+
+                while end < chars.len() {
+                    let direction = keyboard
+                        .neighbours
+                        .get(&chars[end - 1])
+                        .and_then(|neighbours| neighbours.iter().position(|n| n.is_some_and(|n| n == chars[end] || shifted_pair(n, chars[end]))));
+
+                    match direction {
+                        Some(direction) => {
+                            if last_direction != Some(direction) {
+                                turns += 1;
+                                last_direction = Some(direction);
+                            }
+                            shifted += usize::from(is_shifted(chars[end]));
+                            end += 1;
+                        },
+                        None => break,
+                    }
+                }
+
+                if end - start >= 3 {
+                    matches.push(Match {
+                        start,
+                        end,
+                        guesses: spatial_guesses(end - start, turns, shifted, keyboard),
+                        pattern: Pattern::Spatial { turns },
+                    });
+                }
+
+                start = end;
+            }
+        }
+
+        matches
+    }
+
+    fn repeat_matches(&self, chars: &[char]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+
+        while start < chars.len() {
+            // longest run of a repeated base at this position, shortest base on ties
+            let mut best: Option<(usize, usize)> = None; // base length, repetitions
+
+            for base_length in 1..=(chars.len() - start) / 2 {
+                let base = &chars[start..start + base_length];
+                let mut repetitions = 1;
+
+                while start + (repetitions + 1) * base_length <= chars.len()
+                    && &chars[start + repetitions * base_length..start + (repetitions + 1) * base_length] == base {
+                    repetitions += 1;
+                }
+
+                if repetitions > 1 && best.is_none_or(|(length, count)| base_length * repetitions > length * count) {
+                    best = Some((base_length, repetitions));
+                }
+            }
+
+            match best {
+                Some((base_length, repetitions)) => {
+                    let (base_guesses, _) = self.most_guessable(&chars[start..start + base_length]);
+                    let end = start + base_length * repetitions;
+
+                    matches.push(Match {
+                        start,
+                        end,
+                        guesses: base_guesses * repetitions as f64,
+                        pattern: Pattern::Repeat { base_length },
+                    });
+                    start = end;
+                },
+                None => start += 1,
+            }
+        }
+
+        matches
+    }
+
+    fn date_matches(&self, chars: &[char]) -> Vec<Match> {
+        let mut matches = Vec::new();
+
+        for start in 0..chars.len() {
+            for end in start + 4..=chars.len().min(start + 10) {
+                let token = &chars[start..end];
+
+                // a year on its own
+                if end - start == 4 && token.iter().all(|c| c.is_ascii_digit()) {
+                    let year: i64 = token.iter().collect::<String>().parse().unwrap();
+                    if (1900..=2099).contains(&year) {
+                        matches.push(Match {
+                            start,
+                            end,
+                            guesses: self.year_space(year),
+                            pattern: Pattern::Date { year_only: true },
+                        });
+                    }
+                }
+
+                if let Some((year, separator)) = parse_date(token) {
+                    let mut guesses = self.year_space(year) * 365.0;
+                    if separator {
+                        guesses *= 4.0;
+                    }
+
+                    matches.push(Match {
+                        start,
+                        end,
+                        guesses,
+                        pattern: Pattern::Date { year_only: false },
+                    });
+                }
+            }
+        }
+
+        matches
+    }
+
+    // years close to the current one are the most likely
+    fn year_space(&self, year: i64) -> f64 {
+        ((year - self.current_year).abs() as f64).max(20.0)
+    }
+}
+
+fn bruteforce_match(start: usize, end: usize) -> Match {
+    let length = end - start;
+    let min = if length == 1 { MIN_SUBMATCH_GUESSES_SINGLE_CHAR } else { MIN_SUBMATCH_GUESSES_MULTI_CHAR } + 1.0;
+
+    Match {
+        start,
+        end,
+        guesses: BRUTEFORCE_CARDINALITY.powi(length as i32).max(min),
+        pattern: Pattern::Bruteforce,
+    }
+}
+
+// abc, 13579, zyx, ...: a constant step between characters of the same kind
+fn sequence_matches(chars: &[char]) -> Vec<Match> {
+    let kind = |c: char| {
+        if c.is_ascii_lowercase() {
+            Some(0)
+        } else if c.is_ascii_uppercase() {
+            Some(1)
+        } else if c.is_ascii_digit() {
+            Some(2)
+        } else {
+            None
+        }
+    };
+
+    let mut matches = Vec::new();
+    let mut start = 0;
+
+    while start + 2 < chars.len() {
+        let delta = chars[start + 1] as i64 - chars[start] as i64;
+        let mut end = start + 1;
+
+        if kind(chars[start]).is_some() && delta != 0 && delta.abs() <= 5 {
+            while end < chars.len()
+                && kind(chars[end]) == kind(chars[start])
+                && chars[end] as i64 - chars[end - 1] as i64 == delta {
+                end += 1;
+            }
+        }
+
+        if end - start >= 3 {
+            let first = chars[start];
+            let mut base = if "aAzZ019".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            if delta < 0 {
+                base *= 2.0;
+            }
+
+            matches.push(Match {
+                start,
+                end,
+                guesses: base * (end - start) as f64,
+                pattern: Pattern::Sequence,
+            });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+
+    matches
+}
+
+// day, month and year in any common order, with or without a separator.
+// returns the year and whether a separator was used
+fn parse_date(token: &[char]) -> Option<(i64, bool)> {
+    let text: String = token.iter().collect();
+    let separators = ['/', '-', '.', '_', ' ', '\\'];
+
+    let (parts, separator): (Vec<String>, bool) = if text.chars().all(|c| c.is_ascii_digit()) {
+        if text.len() > 8 {
+            return None;
+        }
+        (Vec::new(), false)
+    } else {
+        let separator = token.iter().find(|c| !c.is_ascii_digit())?;
+        if !separators.contains(separator) {
+            return None;
+        }
+        let parts: Vec<String> = text.split(*separator).map(|part| part.to_string()).collect();
+        if parts.len() != 3 || parts.iter().any(|part| part.is_empty() || part.len() > 4 || !part.chars().all(|c| c.is_ascii_digit())) {
+            return None;
+        }
+        (parts, true)
+    };
+
+    let candidates: Vec<[String; 3]> = if separator {
+        vec![[parts[0].clone(), parts[1].clone(), parts[2].clone()]]
+    } else {
+        // every way of splitting the digits into three parts
+        let mut splits = Vec::new();
+        for i in 1..text.len() - 1 {
+            for j in i + 1..text.len() {
+                splits.push([text[..i].to_string(), text[i..j].to_string(), text[j..].to_string()]);
+            }
+        }
+        splits
+    };
+
+    for [a, b, c] in candidates {
+        // year first or last
+        for (year, first, second) in [(&a, &b, &c), (&c, &a, &b), (&c, &b, &a), (&a, &c, &b)] {
+            if first.len() > 2 || second.len() > 2 || !(year.len() == 2 || year.len() == 4) {
+                continue;
+            }
+
+            let (first, second): (u32, u32) = (first.parse().unwrap(), second.parse().unwrap());
+            let valid_day_month = |day: u32, month: u32| (1..=31).contains(&day) && (1..=12).contains(&month);
+            if !valid_day_month(first, second) && !valid_day_month(second, first) {
+                continue;
+            }
+
+            let mut year: i64 = year.parse().unwrap();
+            if year < 100 {
+                year += if year > 50 { 1900 } else { 2000 };
+            }
+            if (1000..=2050).contains(&year) {
+                return Some((year, separator));
+            }
+        }
+    }
+
+    None
+}
+
+// every way of reading the l33t characters of the password, as maps from character to letter
+fn l33t_substitutions(chars: &[char]) -> Vec<HashMap<char, char>> {
+    let mut substitutions = vec![HashMap::new()];
+
+    for (c, letters) in L33T_TABLE {
+        if !chars.contains(&c) {
+            continue;
+        }
+
+        substitutions = substitutions
+            .into_iter()
+            .flat_map(|substitution: HashMap<char, char>| {
+                letters.chars().map(move |letter| {
+                    let mut substitution = substitution.clone();
+                    substitution.insert(c, letter);
+                    substitution
+                })
+            })
+            .take(64)
+            .collect();
+    }
+
+    substitutions.retain(|substitution| !substitution.is_empty());
+    substitutions
+}
+
+fn l33t_variations(token: &[char], substitutions: &HashMap<char, char>) -> f64 {
+    let mut variations = 1.0;
+
+    for (substituted, letter) in substitutions {
+        let s = token.iter().filter(|c| *c == substituted).count();
+        let u = token.iter().filter(|c| c.to_ascii_lowercase() == *letter).count();
+
+        if s == 0 {
+            continue;
+        }
+        if u == 0 {
+            variations *= 2.0;
+        } else {
+            variations *= (1..=s.min(u)).map(|i| binomial(s + u, i)).sum::<f64>();
+        }
+    }
+
+    variations
+}
+
+// Password, passworD and PASSWORD are tried early, other mixes of case need more guesses
+fn uppercase_variations(token: &[char]) -> f64 {
+    let upper = token.iter().filter(|c| c.is_uppercase()).count();
+    let lower = token.iter().filter(|c| c.is_lowercase()).count();
+
+    if upper == 0 {
+        return 1.0;
+    }
+    if lower == 0
+        || (upper == 1 && (token[0].is_uppercase() || token[token.len() - 1].is_uppercase())) {
+        return 2.0;
+    }
+
+    (1..=upper.min(lower)).map(|i| binomial(upper + lower, i)).sum()
+}
+
+fn spatial_guesses(length: usize, turns: usize, shifted: usize, keyboard: &Keyboard) -> f64 {
+    let mut guesses = 0.0;
+
+    for i in 2..=length {
+        for j in 1..=turns.min(i - 1) {
+            guesses += binomial(i - 1, j - 1) * keyboard.starting_positions * keyboard.average_degree.powi(j as i32);
+        }
+    }
+
+    let unshifted = length - shifted;
+    if shifted > 0 {
+        if unshifted == 0 {
+            guesses *= 2.0;
+        } else {
+            guesses *= (1..=shifted.min(unshifted)).map(|i| binomial(length, i)).sum::<f64>();
+        }
+    }
+
+    guesses
+}
+
+// the qwerty layout, each row half a key to the right of the one above
+fn qwerty() -> Keyboard {
+    let mut positions: HashMap<(i64, i64), (char, char)> = HashMap::new();
+    for (y, (row, shifted_row, offset)) in QWERTY.iter().enumerate() {
+        for (x, (key, shifted_key)) in row.chars().zip(shifted_row.chars()).enumerate() {
+            positions.insert(((x + offset) as i64, y as i64), (key, shifted_key));
+        }
+    }
+
+    let directions = [(-1, 0), (0, -1), (1, -1), (1, 0), (0, 1), (-1, 1)];
+    build_keyboard(&positions, &directions)
+}
+
+fn keypad() -> Keyboard {
+    let mut positions: HashMap<(i64, i64), (char, char)> = HashMap::new();
+    for (y, (row, offset)) in KEYPAD.iter().enumerate() {
+        for (x, key) in row.chars().enumerate() {
+            positions.insert(((x + offset) as i64, y as i64), (key, key));
+        }
+    }
+
+    let directions = [(-1, 0), (-1, -1), (0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1)];
+    build_keyboard(&positions, &directions)
+}
+
+fn build_keyboard(positions: &HashMap<(i64, i64), (char, char)>, directions: &[(i64, i64)]) -> Keyboard {
+    let mut graph = HashMap::new();
+
+    for (&(x, y), &(key, shifted_key)) in positions {
+        let neighbours: Vec<Option<char>> = directions
+            .iter()
+            .map(|(dx, dy)| positions.get(&(x + dx, y + dy)).map(|(neighbour, _)| *neighbour))
+            .collect();
+
+        graph.insert(key, neighbours.clone());
+        graph.insert(shifted_key, neighbours);
+    }
+
+    let starting_positions = graph.len() as f64;
+    let average_degree = graph
+        .values()
+        .map(|neighbours| neighbours.iter().flatten().count() as f64)
+        .sum::<f64>() / starting_positions;
+
+    Keyboard {
+        neighbours: graph,
+        starting_positions,
+        average_degree,
+    }
+}
+
+fn is_shifted(c: char) -> bool {
+    c.is_ascii_uppercase() || "~!@#$%^&*()_+{}|:\"<>?".contains(c)
+}
+
+// whether `shifted` is typed with the same key as `key`
+fn shifted_pair(key: char, shifted: char) -> bool {
+    QWERTY.iter().any(|(row, shifted_row, _)| {
+        row.chars().zip(shifted_row.chars()).any(|pair| pair == (key, shifted))
+    })
+}
+
+fn factorial(n: usize) -> f64 {
+    (1..=n).map(|i| i as f64).product()
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+
+    (1..=k).map(|i| (n - k + i) as f64 / i as f64).product()
+}
+
+// feedback is based on the longest match, like zxcvbn. strong passwords get none
+fn feedback(chars: &[char], score: u8, sequence: &[Match]) -> (Option<String>, Vec<String>) {
+    if chars.is_empty() {
+        return (None, vec![
+            String::from("Use a few words, avoid common phrases"),
+            String::from("No need for symbols, digits, or uppercase letters"),
+        ]);
+    }
+    if score > 2 {
+        return (None, Vec::new());
+    }
+
+    let mut suggestions = vec![String::from("Add another word or two. Uncommon words are better")];
+    let longest = match sequence.iter().max_by_key(|m| m.end - m.start) {
+        Some(m) => m,
+        None => return (None, suggestions),
+    };
+
+    let warning = match &longest.pattern {
+        Pattern::Dictionary { rank, common_password, reversed, l33t } => {
+            let token = &chars[longest.start..longest.end];
+
+            if token[0].is_uppercase() && token.iter().skip(1).all(|c| !c.is_uppercase()) {
+                suggestions.push(String::from("Capitalization doesn't help very much"));
+            } else if token.iter().any(|c| c.is_alphabetic()) && token.iter().all(|c| !c.is_lowercase()) {
+                suggestions.push(String::from("All-uppercase is almost as easy to guess as all-lowercase"));
+            }
+            if *reversed {
+                suggestions.push(String::from("Reversed words aren't much harder to guess"));
+            }
+            if *l33t {
+                suggestions.push(String::from("Predictable substitutions like '@' instead of 'a' don't help very much"));
+            }
+
+            if *common_password && sequence.len() == 1 && !*l33t && !*reversed {
+                if *rank <= 10 {
+                    Some(String::from("This is a top-10 common password"))
+                } else if *rank <= 100 {
+                    Some(String::from("This is a top-100 common password"))
+                } else {
+                    Some(String::from("This is a very common password"))
+                }
+            } else if *common_password {
+                Some(String::from("This is similar to a commonly used password"))
+            } else if sequence.len() == 1 {
+                Some(String::from("A word by itself is easy to guess"))
+            } else {
+                None
+            }
+        },
+        Pattern::Spatial { turns } => {
+            suggestions.push(String::from("Use a longer keyboard pattern with more turns"));
+            if *turns == 1 {
+                Some(String::from("Straight rows of keys are easy to guess"))
+            } else {
+                Some(String::from("Short keyboard patterns are easy to guess"))
+            }
+        },
+        Pattern::Repeat { base_length } => {
+            suggestions.push(String::from("Avoid repeated words and characters"));
+            if *base_length == 1 {
+                Some(String::from("Repeats like \"aaa\" are easy to guess"))
+            } else {
+                Some(String::from("Repeats like \"abcabcabc\" are only slightly harder to guess than \"abc\""))
+            }
+        },
+        Pattern::Sequence => {
+            suggestions.push(String::from("Avoid sequences"));
+            Some(String::from("Sequences like abc or 6543 are easy to guess"))
+        },
+        Pattern::Date { year_only } => {
+            suggestions.push(String::from("Avoid dates and years that are associated with you"));
+            if *year_only {
+                Some(String::from("Recent years are easy to guess"))
+            } else {
+                Some(String::from("Dates are often easy to guess"))
+            }
+        },
+        Pattern::Bruteforce => None,
+    };
+
+    (warning, suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the patterns the cheapest guessing sequence splits the password into
+    fn patterns(password: &str) -> Vec<Pattern> {
+        let chars: Vec<char> = password.chars().collect();
+        let (_, sequence) = Estimator::new(&[]).most_guessable(&chars);
+
+        sequence.into_iter().map(|m| m.pattern).collect()
+    }
+
+    #[test]
+    fn finds_keyboard_walks() {
+        for walk in ["wertyuio", "xcvbnm,.", "sdfghjk"] {
+            assert!(matches!(patterns(walk)[..], [Pattern::Spatial { turns: 1 }]), "{}", walk);
+        }
+        assert!(matches!(patterns("qwedcxz")[..], [Pattern::Spatial { turns: 3 }]));
+        assert!(matches!(patterns("WERTYU")[..], [Pattern::Spatial { .. }]));
+        assert!(matches!(patterns("78963")[..], [Pattern::Spatial { turns: 2 }]));
+
+        let estimate = estimate("wertyuio", &[]);
+        assert!(estimate.score <= 1);
+        assert_eq!(estimate.warning.as_deref(), Some("Straight rows of keys are easy to guess"));
+    }
+
+    #[test]
+    fn finds_repeats() {
+        assert!(matches!(patterns("aaaaaaaaaa")[..], [Pattern::Repeat { base_length: 1 }]));
+        assert!(matches!(patterns("xkq7xkq7xkq7")[..], [Pattern::Repeat { base_length: 4 }]));
+
+        // repeating a base barely adds to the guesses of the base
+        let base = estimate("xkq7", &[]).guesses;
+        assert!(estimate("xkq7xkq7xkq7", &[]).guesses < base * 4.0);
+        assert_eq!(estimate("aaaaaaaaaa", &[]).score, 0);
+    }
+
+    #[test]
+    fn finds_sequences() {
+        for sequence in ["abcdefgh", "ZYXWVU", "13579", "98765"] {
+            assert!(matches!(patterns(sequence)[..], [Pattern::Sequence]), "{}", sequence);
+        }
+        assert_eq!(estimate("abcdefgh", &[]).score, 0);
+        assert_eq!(estimate("abcdefgh", &[]).warning.as_deref(), Some("Sequences like abc or 6543 are easy to guess"));
+    }
+
+    #[test]
+    fn finds_dates() {
+        for date in ["13/05/1997", "1997-05-13", "13.5.97", "19970513", "130597"] {
+            assert!(matches!(patterns(date)[..], [Pattern::Date { year_only: false }]), "{}", date);
+        }
+        assert!(matches!(patterns("1987")[..], [Pattern::Date { year_only: true }]));
+        assert!(patterns("99/99/9999").iter().all(|pattern| !matches!(pattern, Pattern::Date { .. })));
+        assert!(estimate("13/05/1997", &[]).score <= 1);
+    }
+
+    #[test]
+    fn finds_l33t_words() {
+        for password in ["p@ssw0rd", "P4$$w0rd"] {
+            assert!(matches!(patterns(password)[..], [Pattern::Dictionary { common_password: true, l33t: true, .. }]), "{}", password);
+        }
+        // substitutions cost more guesses than the plain word, but not many
+        let plain = estimate("password", &[]).guesses;
+        let l33t = estimate("p@ssw0rd", &[]).guesses;
+        assert!(l33t > plain && l33t < plain * 100.0);
+        assert!(estimate("p@ssw0rd", &[]).suggestions.iter().any(|s| s.contains("Predictable substitutions")));
+    }
+
+    #[test]
+    fn finds_dictionary_words() {
+        assert!(matches!(patterns("password")[..], [Pattern::Dictionary { rank: 2, common_password: true, reversed: false, l33t: false }]));
+        assert!(matches!(patterns("drowssap")[..], [Pattern::Dictionary { reversed: true, .. }]));
+        assert_eq!(estimate("password", &[]).score, 0);
+        assert_eq!(estimate("password", &[]).warning.as_deref(), Some("This is a top-10 common password"));
+    }
+
+    #[test]
+    fn user_inputs_are_guessed_early() {
+        let chars: Vec<char> = "mybanksite".chars().collect();
+        let (with_input, sequence) = Estimator::new(&["mybanksite"]).most_guessable(&chars);
+        assert!(matches!(sequence[..], [Match { pattern: Pattern::Dictionary { common_password: false, .. }, .. }]));
+        assert!(with_input < estimate("mybanksite", &[]).guesses);
+    }
+
+    #[test]
+    fn scores_random_passwords_high() {
+        let estimate = estimate("Zq8#mPl2@vX9!kR4", &[]);
+        assert_eq!(estimate.score, 4);
+        assert_eq!(estimate.warning, None);
+        assert!(estimate.suggestions.is_empty());
+        assert_eq!(estimate.describe_crack_time(), "centuries");
+    }
+
+    #[test]
+    fn empty_passwords_score_zero() {
+        let estimate = estimate("", &[]);
+        assert_eq!(estimate.score, 0);
+        assert!(!estimate.suggestions.is_empty());
+    }
+}
@@ -1,5 +1,6 @@
-use crate::{Entry, Error, get_cellar_path, get_absolute_path};
-use crate::store;
+use crate::{Entry, EntryKind, Error, get_cellar_path, get_absolute_path};
+use crate::otp::Otp;
+use crate::{ssh, store};
 use csv::{Writer, ReaderBuilder};
 use std::path::PathBuf;
 
@@ -14,10 +15,16 @@ pub fn export(export_file: &str, master_key: &[u8], overwrite: bool) -> Result<(
 
     let mut writer = Writer::from_path(path).unwrap();
     for entry in &entries {
-        writer.serialize(entry.decrypted(master_key))?;
+        writer.serialize(entry.decrypted(master_key)?)?;
     }
     writer.flush()?;
 
+    // files don't fit in a csv, and importing erases the cellar
+    let attachment_count = store::count_attachments()?;
+    if attachment_count > 0 {
+        eprintln!("Warning: {} attachments were not exported, save them with `vodka attach get`", attachment_count);
+    }
+
     Ok(())
 }
 
@@ -41,14 +48,53 @@ pub fn import(import_file: &str, master_key: &[u8], overwrite: bool) -> Result<(
         let login = record.get(2).unwrap_or("").to_string();
         let password: String = record.get(3).unwrap_or("").to_string();
         let comment = record.get(4).unwrap_or("").to_string();
+        let otp = record.get(5).unwrap_or("");
+        let timestamp = |column: usize| record.get(column).and_then(|value| value.parse::<i64>().ok());
+        let fields = crate::decode_fields(record.get(10).unwrap_or(""), master_key)?;
+        let folder = record.get(11).unwrap_or("");
+        let tags = record.get(12).unwrap_or("");
+        let urls = record.get(13).unwrap_or("");
+        let kind = record.get(14).and_then(EntryKind::parse).unwrap_or(EntryKind::Password);
+        let ssh_confirm = record.get(15) == Some("true");
 
-        crate::add_entry(Entry::new(
+        // the public key is derived again rather than trusted from the file
+        let public_key = match kind {
+            EntryKind::SshKey => Some(ssh::parse_private_key(&password)?.public_key().to_openssh()?),
+            _ => None,
+        };
+
+        let mut entry = Entry::new(
             name,
             login,
             password,
             comment,
             master_key
-        ))?;
+        );
+
+        if !otp.is_empty() {
+            entry.set_otp(Some(&Otp::from_uri(otp)?), master_key);
+        }
+
+        // csv files from older versions have no timestamps, those entries count as new
+        if record.len() > 6 {
+            entry.created_at = timestamp(6);
+            entry.updated_at = timestamp(7);
+            entry.password_changed_at = timestamp(8);
+            entry.last_used_at = timestamp(9);
+        }
+
+        entry.kind = kind;
+        entry.folder = crate::normalize_folder(folder);
+        entry.tags = tags.split(',').filter_map(|tag| crate::parse_tag(tag).ok()).collect();
+        entry.tags.sort();
+        entry.tags.dedup();
+        entry.urls = urls.split_whitespace().map(crate::urls::normalize).collect::<Result<_, _>>()?;
+
+        // fields go in with the entry, under the id it actually gets
+        match public_key {
+            Some(public_key) => store::add_ssh_key(&entry, &public_key, ssh_confirm, &fields)?,
+            None => store::add_entry(&entry, &fields)?,
+        };
     }
     
     Ok(())
@@ -0,0 +1,169 @@
+use crate::{Entry, Error, SearchResult};
+use publicsuffix::{List, Psl};
+use std::sync::OnceLock;
+use url::{Host, Url};
+
+// the Public Suffix List (https://publicsuffix.org), private domains included, so that
+// example.co.uk and example.com are told apart from co.uk, and alice.github.io from bob.github.io
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../wordlists/public_suffix_list.dat");
+
+fn public_suffix_list() -> &'static List {
+    static LIST: OnceLock<List> = OnceLock::new();
+    LIST.get_or_init(|| PUBLIC_SUFFIX_LIST.parse().expect("bundled public suffix list is invalid"))
+}
+
+// how close the url of an entry has to be to the one looked up
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UrlMatch {
+    Domain, // same registrable domain: login.example.com matches www.example.com
+    Host, // same host and port
+    Prefix, // same scheme, host and port, and the entry's path is a prefix of the url's
+}
+
+impl UrlMatch {
+    pub fn parse(s: &str) -> Option<UrlMatch> {
+        match s {
+            "domain" => Some(UrlMatch::Domain),
+            "host" => Some(UrlMatch::Host),
+            "prefix" => Some(UrlMatch::Prefix),
+            _ => None,
+        }
+    }
+}
+
+// "GitHub.com/login?next=x#top" is https://github.com/login. urls without a scheme are
+// taken as https, queries and fragments are dropped as they never identify a site
+pub fn normalize(input: &str) -> Result<String, Error> {
+    let input = input.trim();
+
+    // "localhost:3000" has a port, "mailto:bob@example.com" a scheme
+    let has_scheme = input.contains("://") || match input.split_once(':') {
+        Some((scheme, rest)) => {
+            scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-')
+                && !rest.starts_with(|c: char| c.is_ascii_digit())
+        },
+        None => false,
+    };
+    let with_scheme = if has_scheme { input.to_string() } else { format!("https://{}", input) };
+
+    let mut url = Url::parse(&with_scheme).map_err(|e| Error::InvalidUrl(format!("{} ({})", input, e)))?;
+    if url.host_str().is_none_or(|host| host.is_empty()) {
+        return Err(Error::InvalidUrl(format!("{} has no host", input)));
+    }
+
+    url.set_query(None);
+    url.set_fragment(None);
+
+    Ok(url.to_string())
+}
+
+// example.co.uk for www.example.co.uk. ip addresses are only ever equal to themselves (the
+// list would make 10.0.1.1 and 192.168.1.1 both "1.1"), hosts without one (localhost, bare
+// public suffixes) are their own domain
+fn registrable_domain(url: &Url) -> Option<String> {
+    match url.host()? {
+        Host::Domain(host) => match public_suffix_list().domain(host.as_bytes()) {
+            Some(domain) => Some(String::from_utf8_lossy(domain.trim().as_bytes()).to_string()),
+            None => Some(host.to_string()),
+        },
+        ip => Some(ip.to_string()),
+    }
+}
+
+// (how close, length of the matching path) or None if the entry's url doesn't match at all
+fn closeness(entry_url: &Url, url: &Url) -> Option<(UrlMatch, usize)> {
+    let (entry_host, host) = (entry_url.host_str()?, url.host_str()?);
+
+    if entry_host == host && entry_url.port_or_known_default() == url.port_or_known_default() {
+        let entry_path = entry_url.path().trim_end_matches('/');
+        let path_matches = url.path() == entry_path || url.path().starts_with(&format!("{}/", entry_path));
+
+        if entry_url.scheme() == url.scheme() && path_matches {
+            return Some((UrlMatch::Prefix, entry_path.len()));
+        }
+        return Some((UrlMatch::Host, 0));
+    }
+
+    if registrable_domain(entry_url) == registrable_domain(url) {
+        return Some((UrlMatch::Domain, 0));
+    }
+
+    None
+}
+
+// entries with a url matching at least as closely as `mode`. only the closest ones are
+// returned: an entry for github.com/orgs/work wins over one for github.com on github.com/orgs/work/repo
+pub fn find_by_url(entries: Vec<Entry>, url: &str, mode: UrlMatch) -> Result<SearchResult, Error> {
+    let url = Url::parse(&normalize(url)?).unwrap();
+
+    let mut best: Option<(UrlMatch, usize)> = None;
+    let mut candidates: Vec<Entry> = Vec::new();
+
+    for entry in entries {
+        let closest = entry.urls
+            .iter()
+            .filter_map(|entry_url| Url::parse(entry_url).ok())
+            .filter_map(|entry_url| closeness(&entry_url, &url))
+            .filter(|(closeness, _)| *closeness >= mode)
+            .max();
+
+        let Some(closest) = closest else { continue };
+
+        if best.is_none_or(|best| closest > best) {
+            best = Some(closest);
+            candidates.clear();
+        }
+        if best == Some(closest) {
+            candidates.push(entry);
+        }
+    }
+
+    Ok(match candidates.len() {
+        0 => SearchResult::NoResults,
+        1 => SearchResult::OneResult(candidates.remove(0)),
+        _ => SearchResult::ManyResults(candidates),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closeness_of(entry_url: &str, url: &str) -> Option<UrlMatch> {
+        let entry_url = Url::parse(&normalize(entry_url).unwrap()).unwrap();
+        let url = Url::parse(&normalize(url).unwrap()).unwrap();
+        closeness(&entry_url, &url).map(|(closeness, _)| closeness)
+    }
+
+    #[test]
+    fn ip_addresses_only_match_themselves() {
+        assert_eq!(closeness_of("http://192.168.1.1", "http://10.0.1.1"), None);
+        assert_eq!(closeness_of("192.168.1.1", "10.0.1.1/login"), None);
+        assert_eq!(closeness_of("http://192.168.1.1", "http://192.168.1.1/admin"), Some(UrlMatch::Prefix));
+        assert_eq!(closeness_of("http://192.168.1.1:8080", "http://192.168.1.1"), Some(UrlMatch::Domain));
+        assert_eq!(closeness_of("http://[::1]", "http://[::2]"), None);
+        assert_eq!(closeness_of("http://[::1]", "http://[::1]/x"), Some(UrlMatch::Prefix));
+    }
+
+    #[test]
+    fn localhost_is_its_own_domain() {
+        assert_eq!(closeness_of("localhost:3000", "localhost:3000/app"), Some(UrlMatch::Prefix));
+        assert_eq!(closeness_of("localhost:3000", "localhost:4000"), Some(UrlMatch::Domain));
+        assert_eq!(closeness_of("localhost", "example.com"), None);
+    }
+
+    #[test]
+    fn private_suffixes_separate_sites() {
+        assert_eq!(closeness_of("a.github.io", "b.github.io"), None);
+        assert_eq!(closeness_of("a.github.io", "www.a.github.io"), Some(UrlMatch::Domain));
+        assert_eq!(closeness_of("github.com", "gist.github.com"), Some(UrlMatch::Domain));
+    }
+
+    #[test]
+    fn public_suffixes_separate_sites() {
+        assert_eq!(closeness_of("example.co.uk", "other.co.uk"), None);
+        assert_eq!(closeness_of("login.example.co.uk", "www.example.co.uk"), Some(UrlMatch::Domain));
+        assert_eq!(closeness_of("co.uk", "example.co.uk"), None);
+        assert_eq!(closeness_of("co.uk", "co.uk"), Some(UrlMatch::Prefix));
+    }
+}